@@ -0,0 +1,138 @@
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{read_comic_data, Comic, ComicInfo, ComicInfoError, SchemaError, COMIC_INFO_FILE};
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("can't open the archive at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't read the archive at {1}")]
+    CantReadArchive(#[source] ZipError, PathBuf),
+    #[error("can't find the entry {1} of the archive {2}")]
+    CantFindEntry(#[source] ZipError, String, PathBuf),
+    #[error("can't decompress the entry {1} of the archive {2}")]
+    CantDecompressEntry(#[source] io::Error, String, PathBuf),
+    #[error("failed to deserialize the data.json of the archive {1}")]
+    CantDeserializeComic(#[source] SchemaError, PathBuf),
+    #[error("failed to read the ComicInfo.xml of the archive {1}")]
+    CantReadComicInfo(#[source] ComicInfoError, PathBuf),
+}
+
+/// Return true if this is a file with the cbz or zip extension
+pub fn is_archive(path: &Path) -> bool {
+    let has_archive_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| {
+            extension.eq_ignore_ascii_case("cbz") || extension.eq_ignore_ascii_case("zip")
+        })
+        .unwrap_or(false);
+    has_archive_extension && path.is_file()
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<File>, ArchiveError> {
+    let file = File::open(path).map_err(|err| ArchiveError::CantOpenFile(err, path.into()))?;
+    ZipArchive::new(file).map_err(|err| ArchiveError::CantReadArchive(err, path.into()))
+}
+
+/// An opened zip archive. Its index is only read when it is opened, so its entries can then be
+/// read without parsing it again.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    zip: ZipArchive<File>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, ArchiveError> {
+        Ok(Self {
+            path: path.into(),
+            zip: open_archive(path)?,
+        })
+    }
+
+    /// The name and uncompressed size of all the files (not the directories) in this archive
+    pub fn list_files(&mut self) -> Result<Vec<(String, u64)>, ArchiveError> {
+        let (path, zip) = (&self.path, &mut self.zip);
+        let mut result = Vec::new();
+        for index in 0..zip.len() {
+            let entry = zip
+                .by_index(index)
+                .map_err(|err| ArchiveError::CantReadArchive(err, path.clone()))?;
+            if !entry.is_dir() {
+                result.push((entry.name().to_string(), entry.size()));
+            };
+        }
+        Ok(result)
+    }
+
+    /// Read and decompress the entry with this name
+    pub fn read_entry(&mut self, entry_name: &str) -> Result<Vec<u8>, ArchiveError> {
+        let (path, zip) = (&self.path, &mut self.zip);
+        let mut entry = zip
+            .by_name(entry_name)
+            .map_err(|err| ArchiveError::CantFindEntry(err, entry_name.into(), path.clone()))?;
+        // the size in the header isn't trusted, as the entry may be smaller than it claims
+        let mut result = Vec::new();
+        entry.read_to_end(&mut result).map_err(|err| {
+            ArchiveError::CantDecompressEntry(err, entry_name.into(), path.clone())
+        })?;
+        Ok(result)
+    }
+
+    /// The uncompressed size of the entry with this name
+    pub fn entry_size(&mut self, entry_name: &str) -> Result<u64, ArchiveError> {
+        let (path, zip) = (&self.path, &mut self.zip);
+        let entry = zip
+            .by_name(entry_name)
+            .map_err(|err| ArchiveError::CantFindEntry(err, entry_name.into(), path.clone()))?;
+        Ok(entry.size())
+    }
+}
+
+/// Read the data.json file at the root of this archive. Return `None` if there is no such file.
+pub fn read_data_json(path: &Path) -> Result<Option<Comic>, ArchiveError> {
+    let mut archive = open_archive(path)?;
+    let entry = match archive.by_name("data.json") {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => {
+            return Err(ArchiveError::CantFindEntry(
+                err,
+                "data.json".into(),
+                path.into(),
+            ))
+        }
+    };
+    let comic = read_comic_data(entry)
+        .map_err(|err| ArchiveError::CantDeserializeComic(err, path.into()))?;
+    Ok(Some(comic))
+}
+
+/// Read the ComicInfo.xml file at the root of this archive. Return `None` if there is no such
+/// file.
+pub fn read_comic_info(path: &Path) -> Result<Option<ComicInfo>, ArchiveError> {
+    let mut archive = open_archive(path)?;
+    let entry = match archive.by_name(COMIC_INFO_FILE) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => {
+            return Err(ArchiveError::CantFindEntry(
+                err,
+                COMIC_INFO_FILE.into(),
+                path.into(),
+            ))
+        }
+    };
+    let info = ComicInfo::read(BufReader::new(entry))
+        .map_err(|err| ArchiveError::CantReadComicInfo(err, path.into()))?;
+    Ok(Some(info))
+}
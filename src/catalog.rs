@@ -0,0 +1,748 @@
+use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+use thiserror::Error;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::{
+    comic_to_data, ComicDatabase, ComicEntry, PageNaming, SharedComicDatabase, SourceFile,
+};
+
+/// The version of the tables of the catalog. The catalog is only a copy of the library, so the
+/// tables of another version are dropped and created again.
+const CATALOG_VERSION: i64 = 2;
+
+/// How many comics are read from the library at once when the catalog is updated. The library
+/// is only locked while they are read, not while they are written.
+const SYNC_BATCH_SIZE: usize = 256;
+
+const CREATE_TABLES: &str = "
+    CREATE TABLE comics (
+        id INTEGER PRIMARY KEY,
+        reference TEXT NOT NULL,
+        slug TEXT,
+        name TEXT,
+        description TEXT,
+        path TEXT NOT NULL,
+        location TEXT NOT NULL,
+        modified INTEGER,
+        naming TEXT NOT NULL,
+        data TEXT NOT NULL,
+        pages_listed INTEGER NOT NULL
+    );
+    CREATE TABLE keywords (
+        comic_id INTEGER NOT NULL,
+        category TEXT NOT NULL,
+        keyword TEXT NOT NULL
+    );
+    CREATE INDEX keywords_by_comic ON keywords (comic_id);
+    CREATE INDEX keywords_by_name ON keywords (category, keyword);
+    CREATE TABLE translations (
+        comic_id INTEGER NOT NULL,
+        language TEXT NOT NULL,
+        translation TEXT NOT NULL,
+        is_self INTEGER NOT NULL
+    );
+    CREATE INDEX translations_by_comic ON translations (comic_id);
+    CREATE INDEX translations_by_language ON translations (language);
+    CREATE TABLE pages (
+        comic_id INTEGER NOT NULL,
+        chapter INTEGER NOT NULL,
+        page INTEGER NOT NULL,
+        document INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        size INTEGER,
+        PRIMARY KEY (comic_id, chapter, page, document)
+    );
+";
+
+const DROP_TABLES: &str = "
+    DROP TABLE IF EXISTS comics;
+    DROP TABLE IF EXISTS keywords;
+    DROP TABLE IF EXISTS translations;
+    DROP TABLE IF EXISTS pages;
+";
+
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("can't open the catalog at {1}")]
+    CantOpen(#[source] rusqlite::Error, PathBuf),
+    #[error("the catalog query failed")]
+    Query(#[from] rusqlite::Error),
+    #[error("can't serialize the comic {1}")]
+    CantSerializeComic(#[source] serde_json::Error, usize),
+}
+
+/// The comics of a [`ComicDatabase`] in an embedded SQLite database, with their keywords,
+/// translations and pages.
+///
+/// When a library has a catalog, its keywords and the pages of its comics are read from it,
+/// instead of being kept in memory. It is filled with [`sync_catalog`] once the library is
+/// loaded, and updated with [`sync_catalog_comics`] after each rescan. It can also be queried
+/// by other tools (the tables are documented in the README).
+#[derive(Debug)]
+pub struct Catalog {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+    /// True once it contain the library loaded by this process
+    synced: AtomicBool,
+    /// Held while the catalog is updated, so the comics are written in the order they are read
+    syncing: Mutex<()>,
+}
+
+/// What the pages of a comic in the catalog were listed from. They are only used while the
+/// comic is still the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CatalogState {
+    path: String,
+    location: String,
+    /// The modification time of the page source, in nanoseconds since the epoch
+    modified: Option<i64>,
+    naming: String,
+    data: String,
+}
+
+impl CatalogState {
+    pub(crate) fn of(entry: &ComicEntry, naming: &PageNaming) -> Result<Self, CatalogError> {
+        let serialize_error = |err| CatalogError::CantSerializeComic(err, entry.comic.id);
+        Ok(Self {
+            path: entry.path.to_string_lossy().into_owned(),
+            location: entry.pages.location().to_string_lossy().into_owned(),
+            modified: entry
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|modified| modified.as_nanos() as i64),
+            naming: serde_json::to_string(naming).map_err(serialize_error)?,
+            data: comic_to_data(&entry.comic)
+                .map_err(serialize_error)?
+                .to_string(),
+        })
+    }
+
+    /// Read the state from the `path`, `location`, `modified`, `naming` and `data` columns,
+    /// starting at the column `first`
+    fn from_row(row: &Row, first: usize) -> rusqlite::Result<Self> {
+        Ok(Self {
+            path: row.get(first)?,
+            location: row.get(first + 1)?,
+            modified: row.get(first + 2)?,
+            naming: row.get(first + 3)?,
+            data: row.get(first + 4)?,
+        })
+    }
+}
+
+/// A comic of the library, as it is written in the catalog
+struct CatalogComic {
+    id: i64,
+    reference: String,
+    slug: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    state: CatalogState,
+    keywords: Vec<(String, String)>,
+    /// The language, the translation, and whether it is this comic
+    translations: Vec<(String, String, bool)>,
+    /// The documents of the pages, or `None` if the pages can't be listed
+    pages: Option<Vec<CatalogDocument>>,
+}
+
+/// A document of a page, as it is written in the catalog
+struct CatalogDocument {
+    chapter: i64,
+    page: i64,
+    /// The position of the document in the page
+    document: i64,
+    name: String,
+    size: Option<i64>,
+}
+
+impl CatalogComic {
+    /// Read the comic with this id from `comic_database`. Return `None` if it doesn't exist.
+    fn read(comic_database: &ComicDatabase, comic_id: usize) -> Result<Option<Self>, CatalogError> {
+        let entry = match comic_database.comics().get(comic_id) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        // the comic exist, so it has a naming
+        let naming = comic_database.get_page_naming(comic_id).unwrap();
+        let comic = &entry.comic;
+        let pages = comic_database
+            .get_comic_navigation(comic_id)
+            .ok()
+            .map(|navigation| {
+                let mut pages = Vec::new();
+                for (page_id, page) in navigation.pages() {
+                    for (index, document) in page.documents().iter().enumerate() {
+                        pages.push(CatalogDocument {
+                            chapter: page_id.chapter as i64,
+                            page: page_id.page as i64,
+                            document: index as i64,
+                            name: document.name().to_string(),
+                            size: document.size().map(|size| size as i64),
+                        });
+                    }
+                }
+                pages
+            });
+        Ok(Some(Self {
+            id: comic_id as i64,
+            reference: comic.reference().to_string(),
+            slug: comic.slug.clone(),
+            name: comic.comic_name.clone(),
+            description: comic.description.clone(),
+            state: CatalogState::of(entry, naming)?,
+            keywords: comic
+                .keywords
+                .iter()
+                .flat_map(|(category, keywords)| {
+                    keywords
+                        .iter()
+                        .map(move |keyword| (category.clone(), keyword.clone()))
+                })
+                .collect(),
+            translations: comic
+                .translations
+                .iter()
+                .map(|(language, translation)| {
+                    (
+                        language.clone(),
+                        translation.to_string(),
+                        comic.is_referenced_by(translation),
+                    )
+                })
+                .collect(),
+            pages,
+        }))
+    }
+}
+
+impl Catalog {
+    /// Open the catalog at `path`, creating it if it doesn't exist
+    pub fn open(path: PathBuf) -> Result<Self, CatalogError> {
+        let connection = Connection::open(&path)
+            .and_then(Self::prepare)
+            .map_err(|err| CatalogError::CantOpen(err, path.clone()))?;
+        Ok(Self::with_connection(path, connection))
+    }
+
+    #[cfg(test)]
+    fn open_in_memory() -> Self {
+        let connection = Connection::open_in_memory()
+            .and_then(Self::prepare)
+            .unwrap();
+        Self::with_connection(PathBuf::from(":memory:"), connection)
+    }
+
+    /// Create the tables, if they don't exist in the current version
+    fn prepare(connection: Connection) -> rusqlite::Result<Connection> {
+        let version: i64 =
+            connection.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+        if version != CATALOG_VERSION {
+            connection.execute_batch(DROP_TABLES)?;
+            connection.execute_batch(CREATE_TABLES)?;
+            connection.pragma_update(None, "user_version", &CATALOG_VERSION)?;
+        };
+        Ok(connection)
+    }
+
+    fn with_connection(path: PathBuf, connection: Connection) -> Self {
+        Self {
+            path,
+            connection: Mutex::new(connection),
+            synced: AtomicBool::new(false),
+            syncing: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True once the catalog was filled with [`sync_catalog`]. Before that, it may still
+    /// contain the library of a previous start.
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::SeqCst)
+    }
+
+    /// The state of each comic in the catalog
+    fn states(&self) -> Result<HashMap<usize, CatalogState>, CatalogError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare_cached("SELECT id, path, location, modified, naming, data FROM comics")?;
+        let rows = statement.query_map(NO_PARAMS, |row| {
+            Ok((
+                row.get::<_, i64>(0)? as usize,
+                CatalogState::from_row(row, 1)?,
+            ))
+        })?;
+        let mut states = HashMap::new();
+        for row in rows {
+            let (comic_id, state) = row?;
+            states.insert(comic_id, state);
+        }
+        Ok(states)
+    }
+
+    /// Write these comics, replacing the ones with the same id, and remove the comics with the
+    /// ids in `removed`
+    fn write(&self, comics: &[CatalogComic], removed: &[usize]) -> Result<(), CatalogError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let comic_ids = comics
+                .iter()
+                .map(|comic| comic.id)
+                .chain(removed.iter().map(|comic_id| *comic_id as i64));
+            for comic_id in comic_ids {
+                for table in &["keywords", "translations", "pages"] {
+                    transaction
+                        .prepare_cached(&format!("DELETE FROM {} WHERE comic_id = ?", table))?
+                        .execute(params![comic_id])?;
+                }
+                transaction
+                    .prepare_cached("DELETE FROM comics WHERE id = ?")?
+                    .execute(params![comic_id])?;
+            }
+
+            let mut insert_comic = transaction.prepare_cached(
+                "INSERT INTO comics (id, reference, slug, name, description, path, location, modified, naming, data, pages_listed) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            let mut insert_keyword = transaction.prepare_cached(
+                "INSERT INTO keywords (comic_id, category, keyword) VALUES (?, ?, ?)",
+            )?;
+            let mut insert_translation = transaction.prepare_cached(
+                "INSERT INTO translations (comic_id, language, translation, is_self) VALUES (?, ?, ?, ?)",
+            )?;
+            let mut insert_page = transaction.prepare_cached(
+                "INSERT INTO pages (comic_id, chapter, page, document, name, size) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            for comic in comics {
+                let state = &comic.state;
+                insert_comic.execute(params![
+                    comic.id,
+                    comic.reference,
+                    comic.slug,
+                    comic.name,
+                    comic.description,
+                    state.path,
+                    state.location,
+                    state.modified,
+                    state.naming,
+                    state.data,
+                    comic.pages.is_some()
+                ])?;
+                for (category, keyword) in &comic.keywords {
+                    insert_keyword.execute(params![comic.id, category, keyword])?;
+                }
+                for (language, translation, is_self) in &comic.translations {
+                    insert_translation.execute(params![
+                        comic.id,
+                        language,
+                        translation,
+                        is_self
+                    ])?;
+                }
+                for document in comic.pages.iter().flatten() {
+                    insert_page.execute(params![
+                        comic.id,
+                        document.chapter,
+                        document.page,
+                        document.document,
+                        document.name,
+                        document.size
+                    ])?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// The files of the pages of a comic, if the catalog has them for this state of the comic
+    pub(crate) fn pages(
+        &self,
+        comic_id: usize,
+        state: &CatalogState,
+    ) -> Result<Option<Vec<SourceFile>>, CatalogError> {
+        // without a modification time, there is no way to know if the pages changed
+        if state.modified.is_none() {
+            return Ok(None);
+        };
+        let connection = self.connection.lock().unwrap();
+        let stored = connection
+            .prepare_cached(
+                "SELECT pages_listed, path, location, modified, naming, data FROM comics WHERE id = ?",
+            )?
+            .query_row(params![comic_id as i64], |row| {
+                Ok((row.get::<_, bool>(0)?, CatalogState::from_row(row, 1)?))
+            })
+            .optional()?;
+        if !matches!(stored, Some((true, stored)) if stored == *state) {
+            return Ok(None);
+        };
+
+        let mut statement = connection.prepare_cached(
+            "SELECT name, size FROM pages WHERE comic_id = ? ORDER BY chapter, page, document",
+        )?;
+        let rows = statement.query_map(params![comic_id as i64], |row| {
+            Ok(SourceFile {
+                name: row.get(0)?,
+                size: row.get::<_, Option<i64>>(1)?.map(|size| size as u64),
+            })
+        })?;
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+        Ok(Some(files))
+    }
+
+    /// The keyword categories, with their keywords, both sorted by name. The `translation`
+    /// category list the languages of the comics that are their own translation.
+    pub fn keyword_list(&self) -> Result<Vec<(String, Vec<String>)>, CatalogError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT category, keyword FROM keywords
+            UNION SELECT 'translation', language FROM translations WHERE is_self
+            ORDER BY 1, 2",
+        )?;
+        let rows = statement.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut categories: Vec<(String, Vec<String>)> = Vec::new();
+        for row in rows {
+            let (category, keyword): (String, String) = row?;
+            match categories.last_mut() {
+                Some((last_category, keywords)) if *last_category == category => {
+                    keywords.push(keyword)
+                }
+                _ => categories.push((category, vec![keyword])),
+            };
+        }
+        Ok(categories)
+    }
+
+    /// The ids of the comics with this keyword, in id order
+    pub fn comics_with_keyword(
+        &self,
+        category: &str,
+        keyword: &str,
+    ) -> Result<Vec<usize>, CatalogError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT comic_id FROM keywords WHERE category = ?1 AND keyword = ?2
+            UNION SELECT comic_id FROM translations
+                WHERE ?1 = 'translation' AND language = ?2 AND is_self
+            ORDER BY 1",
+        )?;
+        let rows = statement.query_map(params![category, keyword], |row| row.get::<_, i64>(0))?;
+        let mut comic_ids = Vec::new();
+        for row in rows {
+            comic_ids.push(row? as usize);
+        }
+        Ok(comic_ids)
+    }
+}
+
+/// Update the catalog of `comic_database`, if it has one, so it contain the same comics. Only
+/// the comics that aren't in the catalog, or whose folder, data or pages changed, are written.
+///
+/// The comics are read in small batches, so the library isn't locked while the catalog is
+/// written. Once they are written, their pages are no longer kept in memory. If it fails, the
+/// catalog is no longer considered synced.
+pub fn sync_catalog(comic_database: &SharedComicDatabase) -> Result<(), CatalogError> {
+    let catalog = match comic_database.read().unwrap().catalog() {
+        Some(catalog) => catalog,
+        None => return Ok(()),
+    };
+    let _syncing = catalog.syncing.lock().unwrap();
+    let result = fill_catalog(&catalog, comic_database);
+    catalog.synced.store(result.is_ok(), Ordering::SeqCst);
+    result
+}
+
+/// Update the comics with these ids in the catalog of `comic_database`, if it has one, like
+/// after they were rescanned. The ones that are no longer in the library are removed from the
+/// catalog. If the catalog isn't synced, it is filled like with [`sync_catalog`] instead.
+pub fn sync_catalog_comics(
+    comic_database: &SharedComicDatabase,
+    comic_ids: &[usize],
+) -> Result<(), CatalogError> {
+    let catalog = match comic_database.read().unwrap().catalog() {
+        Some(catalog) => catalog,
+        None => return Ok(()),
+    };
+    let _syncing = catalog.syncing.lock().unwrap();
+    let result = if catalog.is_synced() {
+        write_comics(&catalog, comic_database, comic_ids)
+    } else {
+        fill_catalog(&catalog, comic_database)
+    };
+    catalog.synced.store(result.is_ok(), Ordering::SeqCst);
+    result
+}
+
+/// Write the comics of `comic_database` that changed since they were written in `catalog`, and
+/// remove the ones that are no longer in the library
+fn fill_catalog(
+    catalog: &Catalog,
+    comic_database: &SharedComicDatabase,
+) -> Result<(), CatalogError> {
+    let mut outdated = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut removed = catalog.states()?;
+    let comic_ids: Vec<usize> = comic_database.read().unwrap().comics().keys().collect();
+    for batch in comic_ids.chunks(SYNC_BATCH_SIZE) {
+        let comic_database = comic_database.read().unwrap();
+        for comic_id in batch {
+            let entry = match comic_database.comics().get(*comic_id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // the comic exist, so it has a naming
+            let naming = comic_database.get_page_naming(*comic_id).unwrap();
+            let state = CatalogState::of(entry, naming)?;
+            match removed.remove(comic_id) {
+                Some(stored) if stored == state => unchanged.push(*comic_id),
+                _ => outdated.push(*comic_id),
+            };
+        }
+    }
+
+    let removed: Vec<usize> = removed.into_iter().map(|(comic_id, _)| comic_id).collect();
+    catalog.write(&[], &removed)?;
+    write_comics(catalog, comic_database, &outdated)?;
+    comic_database
+        .read()
+        .unwrap()
+        .forget_navigations(&unchanged);
+    Ok(())
+}
+
+/// Write again these comics of `comic_database` in `catalog`, or remove them if they are no
+/// longer in the library
+fn write_comics(
+    catalog: &Catalog,
+    comic_database: &SharedComicDatabase,
+    comic_ids: &[usize],
+) -> Result<(), CatalogError> {
+    for batch in comic_ids.chunks(SYNC_BATCH_SIZE) {
+        let mut comics = Vec::new();
+        let mut removed = Vec::new();
+        {
+            let comic_database = comic_database.read().unwrap();
+            for comic_id in batch {
+                match CatalogComic::read(&comic_database, *comic_id)? {
+                    Some(comic) => comics.push(comic),
+                    None => removed.push(*comic_id),
+                };
+            }
+        }
+        catalog.write(&comics, &removed)?;
+        comic_database.read().unwrap().forget_navigations(batch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::sync::{Arc, RwLock};
+
+    use crate::{ComicNavigation, DuplicateIdPolicy};
+
+    /// A library with three comics, in a folder unique to this test
+    fn test_library(name: &str) -> PathBuf {
+        let library =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&library);
+        let comics = [
+            (
+                "a",
+                r#"{"id": 1, "keywords": {"genre": ["sf", "comedy"]}, "translations": [["en", 1], ["fr", 2]]}"#,
+            ),
+            (
+                "b",
+                r#"{"id": 2, "keywords": {"genre": ["sf"]}, "translations": [["en", 1], ["fr", 2]]}"#,
+            ),
+            (
+                "c",
+                r#"{"id": 3, "slug": "third", "keywords": {"author": ["someone"]}, "translations": []}"#,
+            ),
+        ];
+        for (folder, data) in &comics {
+            let folder = library.join(folder);
+            create_dir_all(&folder).unwrap();
+            write(folder.join("data.json"), data).unwrap();
+            write(folder.join("00000-00000.png"), "page").unwrap();
+            write(folder.join("00000-00001.png"), "page").unwrap();
+        }
+        library
+    }
+
+    fn load_with_catalog(library: &Path, catalog: Arc<Catalog>) -> SharedComicDatabase {
+        let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        comic_database.set_catalog(catalog);
+        comic_database.load_from_dir(library.to_path_buf()).unwrap();
+        Arc::new(RwLock::new(comic_database))
+    }
+
+    fn keyword(category: &str, keywords: &[&str]) -> (String, Vec<String>) {
+        let keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+        (category.to_string(), keywords)
+    }
+
+    #[test]
+    fn keywords_are_read_from_the_catalog() {
+        let library = test_library("catalog-keywords");
+        let catalog = Arc::new(Catalog::open_in_memory());
+        let comic_database = load_with_catalog(&library, catalog.clone());
+        // the keywords aren't kept in memory, but can be found before the catalog is filled
+        assert!(comic_database.read().unwrap().keywords().is_empty());
+        assert_eq!(
+            comic_database
+                .read()
+                .unwrap()
+                .comics_with_keyword("genre", "sf"),
+            vec![1, 2]
+        );
+
+        sync_catalog(&comic_database).unwrap();
+        assert!(catalog.is_synced());
+        assert_eq!(
+            catalog.keyword_list().unwrap(),
+            vec![
+                keyword("author", &["someone"]),
+                keyword("genre", &["comedy", "sf"]),
+                keyword("translation", &["en", "fr"]),
+            ]
+        );
+        assert_eq!(
+            catalog.comics_with_keyword("genre", "sf").unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            catalog.comics_with_keyword("genre", "comedy").unwrap(),
+            vec![1]
+        );
+        assert_eq!(
+            catalog.comics_with_keyword("translation", "fr").unwrap(),
+            vec![2]
+        );
+        assert!(catalog
+            .comics_with_keyword("genre", "fr")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            comic_database.read().unwrap().keyword_list(),
+            catalog.keyword_list().unwrap()
+        );
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn pages_are_read_from_the_catalog() {
+        let library = test_library("catalog-pages");
+        let catalog = Arc::new(Catalog::open_in_memory());
+        let comic_database = load_with_catalog(&library, catalog.clone());
+        sync_catalog(&comic_database).unwrap();
+
+        {
+            let comic_database = comic_database.read().unwrap();
+            let entry = &comic_database.comics()[3];
+            let state = CatalogState::of(entry, &PageNaming::Standard).unwrap();
+            let names: Vec<String> = catalog
+                .pages(3, &state)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect();
+            assert_eq!(names, vec!["00000-00000.png", "00000-00001.png"]);
+            // the pages of another state of the comic aren't used
+            let naming = PageNaming::SingleChapter;
+            let other_state = CatalogState::of(entry, &naming).unwrap();
+            assert!(catalog.pages(3, &other_state).unwrap().is_none());
+
+            // the navigation isn't kept in memory, and is computed from the catalog
+            assert!(!comic_database.is_navigation_cached(3));
+            let navigation = comic_database.get_comic_navigation(3).unwrap();
+            let listed =
+                ComicNavigation::from_source(&*entry.pages, &entry.comic, &PageNaming::Standard);
+            assert_eq!(*navigation, listed.unwrap());
+            assert!(!comic_database.is_navigation_cached(3));
+        }
+
+        // a catalog filled by a previous start is reused, once the comic didn't change
+        let comic_database = load_with_catalog(&library, catalog);
+        assert_eq!(
+            comic_database
+                .read()
+                .unwrap()
+                .get_comic_navigation(1)
+                .unwrap()
+                .page_count(),
+            2
+        );
+        assert!(!comic_database.read().unwrap().is_navigation_cached(1));
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn changed_and_removed_comics_are_synced() {
+        let library = test_library("catalog-changes");
+        let catalog = Arc::new(Catalog::open_in_memory());
+        let comic_database = load_with_catalog(&library, catalog.clone());
+        sync_catalog(&comic_database).unwrap();
+
+        write(
+            library.join("c/data.json"),
+            r#"{"id": 3, "keywords": {"author": ["someone else"]}, "translations": []}"#,
+        )
+        .unwrap();
+        remove_dir_all(library.join("b")).unwrap();
+        let changes = {
+            let mut comic_database = comic_database.write().unwrap();
+            let removed = comic_database.rescan_comic(2).unwrap();
+            assert!(removed.removed);
+            comic_database.rescan_comic(3).unwrap();
+            vec![2, 3]
+        };
+        // the catalog is only updated for the rescanned comics
+        assert_eq!(
+            catalog.comics_with_keyword("genre", "sf").unwrap(),
+            vec![1, 2]
+        );
+        sync_catalog_comics(&comic_database, &changes).unwrap();
+        assert_eq!(catalog.comics_with_keyword("genre", "sf").unwrap(), vec![1]);
+        assert!(catalog
+            .comics_with_keyword("author", "someone")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            catalog
+                .comics_with_keyword("author", "someone else")
+                .unwrap(),
+            vec![3]
+        );
+        assert_eq!(catalog.states().unwrap().len(), 2);
+
+        // a full sync remove the comics that are no longer in the library, and keep the others
+        let comic_database = Arc::new(RwLock::new({
+            let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+            comic_database.set_catalog(catalog.clone());
+            comic_database
+        }));
+        sync_catalog(&comic_database).unwrap();
+        assert!(catalog.states().unwrap().is_empty());
+        assert!(catalog.keyword_list().unwrap().is_empty());
+
+        remove_dir_all(&library).unwrap();
+    }
+}
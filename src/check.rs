@@ -0,0 +1,457 @@
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{ComicDatabase, DuplicateIdPolicy, LoadProblem, PageNaming};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckSeverity {
+    /// The library doesn't follow the specification
+    Error,
+    /// The library follow the specification, but this is probably a mistake
+    Warning,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckIssueKind {
+    /// A library root, a comic folder or a data.json file can't be read or decoded
+    LoadError,
+    /// Two comics share the same id
+    DuplicateId,
+    /// The comic has `found` set to false, and is ignored
+    NotFound,
+    /// The pages of a comic can't be listed, or one of them doesn't follow the `xxxxx-yyyyy.ext` naming
+    InvalidPage,
+    /// A translation point to an id that doesn't exist
+    MissingTranslation,
+    /// A translation doesn't reference back the comic that reference it
+    AsymmetricTranslation,
+    /// A chapter number has no page
+    ChapterGap,
+    /// A page number is missing inside a chapter
+    PageGap,
+    /// A page file (or archive entry) can't be read, or is empty
+    UnreadableImage,
+    /// The number of pages differ from the `page_count` of the comic
+    PageCountMismatch,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckIssue {
+    pub severity: CheckSeverity,
+    pub kind: CheckIssueKind,
+    pub comic_id: Option<usize>,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+/// The result of [`check_library`]
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CheckReport {
+    /// The number of comics that could be loaded
+    pub comic_count: usize,
+    pub issues: Vec<CheckIssue>,
+}
+
+impl CheckReport {
+    fn push(
+        &mut self,
+        severity: CheckSeverity,
+        kind: CheckIssueKind,
+        comic_id: Option<usize>,
+        path: Option<&Path>,
+        message: String,
+    ) {
+        self.issues.push(CheckIssue {
+            severity,
+            kind,
+            comic_id,
+            path: path.map(|x| x.to_path_buf()),
+            message,
+        });
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == CheckSeverity::Error)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == CheckSeverity::Warning)
+            .count()
+    }
+
+    /// The exit status of `marblecomic check`: 1 if there is at least one error, 0 otherwise
+    pub fn exit_code(&self) -> i32 {
+        if self.error_count() == 0 {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Check that the comics in those library roots follow the comic specification of the README.
+/// `page_namings` is the page naming of the roots that don't use the standard one.
+pub fn check_library(
+    roots: &[PathBuf],
+    page_namings: &HashMap<PathBuf, PageNaming>,
+) -> CheckReport {
+    let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+    for (root, naming) in page_namings {
+        comic_database.set_page_naming(root.clone(), naming.clone());
+    }
+    // the comics with invalid pages are kept, so the translations pointing to them still resolve
+    comic_database.load_from_dirs_recording_problems(roots);
+    check_database(&comic_database)
+}
+
+/// Check a database loaded with [`ComicDatabase::load_from_dirs_lenient`]. The comics whose
+/// navigation can't be computed are reported as invalid pages.
+pub fn check_database(comic_database: &ComicDatabase) -> CheckReport {
+    use CheckIssueKind::*;
+    use CheckSeverity::*;
+
+    let mut report = CheckReport {
+        comic_count: comic_database.comics().len(),
+        issues: Vec::new(),
+    };
+
+    for problem in comic_database.load_problems() {
+        match problem {
+            LoadProblem::Load(_) => report.push(Error, LoadError, None, None, problem.to_string()),
+            LoadProblem::Navigation(comic_id, _) => report.push(
+                Error,
+                InvalidPage,
+                Some(*comic_id),
+                None,
+                problem.to_string(),
+            ),
+        }
+    }
+
+    for duplicate in comic_database.duplicate_ids() {
+        report.push(
+            Error,
+            DuplicateId,
+            comic_database.resolve(&duplicate.id),
+            Some(&duplicate.second_path),
+            duplicate.to_string(),
+        );
+    }
+
+    for path in comic_database.not_found_paths() {
+        report.push(
+            Warning,
+            NotFound,
+            None,
+            Some(path),
+            "the comic has found set to false, and is ignored".into(),
+        );
+    }
+
+    for (comic_id, entry) in comic_database.comics() {
+        for (language, translation_id) in &entry.comic.translations {
+            if entry.comic.is_referenced_by(translation_id) {
+                continue;
+            };
+            match comic_database.get_comic_by_ref(translation_id) {
+                None => report.push(
+                    Error,
+                    MissingTranslation,
+                    Some(comic_id),
+                    Some(&entry.path),
+                    format!(
+                        "the {} translation point to the comic {}, which doesn't exist",
+                        language, translation_id
+                    ),
+                ),
+                Some(translation) => {
+                    if !translation
+                        .translations
+                        .iter()
+                        .any(|(_, back_id)| entry.comic.is_referenced_by(back_id))
+                    {
+                        report.push(
+                            Warning,
+                            AsymmetricTranslation,
+                            Some(comic_id),
+                            Some(&entry.path),
+                            format!(
+                                "the {} translation point to the comic {}, which doesn't list this comic as a translation",
+                                language, translation_id
+                            ),
+                        )
+                    };
+                }
+            };
+        }
+
+        let navigation = match comic_database.get_comic_navigation(comic_id) {
+            Ok(navigation) => navigation,
+            Err(err) => {
+                report.push(
+                    Error,
+                    InvalidPage,
+                    Some(comic_id),
+                    Some(&entry.path),
+                    LoadProblem::Navigation(comic_id, err).to_string(),
+                );
+                continue;
+            }
+        };
+        for chapter_id in navigation.missing_chapters() {
+            report.push(
+                Warning,
+                ChapterGap,
+                Some(comic_id),
+                Some(&entry.path),
+                format!("the chapter {} has no page", chapter_id),
+            );
+        }
+        for page_id in navigation.missing_pages() {
+            report.push(
+                Warning,
+                PageGap,
+                Some(comic_id),
+                Some(&entry.path),
+                format!("the {} is missing", page_id),
+            );
+        }
+        if let Some(page_count) = entry.comic.page_count {
+            if navigation.page_count() != page_count {
+                report.push(
+                    Warning,
+                    PageCountMismatch,
+                    Some(comic_id),
+                    Some(&entry.path),
+                    format!(
+                        "the comic has {} pages, but should have {}",
+                        navigation.page_count(),
+                        page_count
+                    ),
+                );
+            };
+        };
+        for (page_id, page) in navigation.pages() {
+            for (index, document) in page.documents().iter().enumerate() {
+                // read the whole file, so a truncated archive entry is detected
+                let read_result = comic_database
+                    .open_page(comic_id, page_id, index)
+                    .map_err(|err| err.to_string())
+                    .and_then(|mut page| {
+                        io::copy(&mut page, &mut io::sink()).map_err(|err| err.to_string())
+                    });
+                let message = match read_result {
+                    Ok(0) => "the page file is empty".to_string(),
+                    Ok(_) => continue,
+                    Err(err) => format!("can't read the page file: {}", err),
+                };
+                report.push(
+                    Error,
+                    UnreadableImage,
+                    Some(comic_id),
+                    Some(&entry.pages.location().join(document.name())),
+                    message,
+                );
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    /// Write a comic folder with this data.json and these pages (with their content)
+    fn write_comic(library: &Path, folder: &str, data: &str, pages: &[(&str, &str)]) {
+        let folder = library.join(folder);
+        create_dir_all(&folder).unwrap();
+        write(folder.join("data.json"), data).unwrap();
+        for (name, content) in pages {
+            write(folder.join(name), content).unwrap();
+        }
+    }
+
+    fn test_library(name: &str) -> PathBuf {
+        let library =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&library);
+        create_dir_all(&library).unwrap();
+        library
+    }
+
+    /// The kind, severity and comic of each issue, sorted
+    fn issues_of(report: &CheckReport) -> Vec<(String, CheckSeverity, Option<usize>)> {
+        let mut issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (format!("{:?}", issue.kind), issue.severity, issue.comic_id))
+            .collect();
+        issues.sort_by(|first, second| (&first.0, first.2).cmp(&(&second.0, second.2)));
+        issues
+    }
+
+    #[test]
+    fn valid_libraries_have_no_issue() {
+        let library = test_library("check-valid");
+        write_comic(
+            &library,
+            "en",
+            r#"{"id": 1, "keywords": {}, "translations": [["en", 1], ["fr", 2]], "page_count": 2}"#,
+            &[("00000-00000.png", "page"), ("00000-00001.png", "page")],
+        );
+        write_comic(
+            &library,
+            "fr",
+            r#"{"id": 2, "keywords": {}, "translations": [["en", 1], ["fr", 2]]}"#,
+            &[("00000-00000.png", "page")],
+        );
+        let report = check_library(&[library.clone()], &HashMap::new());
+        assert_eq!(report.comic_count, 2);
+        assert!(report.issues.is_empty());
+        assert_eq!(report.exit_code(), 0);
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        use CheckSeverity::*;
+
+        let library = test_library("check-problems");
+        let page = [("00000-00000.png", "page")];
+        // the translation isn't listed back by the comic 2
+        write_comic(
+            &library,
+            "asymmetric",
+            r#"{"id": 1, "keywords": {}, "translations": [["fr", 2]]}"#,
+            &page,
+        );
+        write_comic(
+            &library,
+            "translation",
+            r#"{"id": 2, "keywords": {}, "translations": []}"#,
+            &page,
+        );
+        write_comic(
+            &library,
+            "duplicate",
+            r#"{"id": 2, "keywords": {}, "translations": []}"#,
+            &page,
+        );
+        write_comic(
+            &library,
+            "missing-translation",
+            r#"{"id": 3, "keywords": {}, "translations": [["de", 99]]}"#,
+            &page,
+        );
+        // no chapter 1, no page 1 in the chapter 0, and one page less than declared
+        write_comic(
+            &library,
+            "gaps",
+            r#"{"id": 4, "keywords": {}, "translations": [], "page_count": 4}"#,
+            &[
+                ("00000-00000.png", "page"),
+                ("00000-00002.png", "page"),
+                ("00002-00000.png", "page"),
+            ],
+        );
+        write_comic(
+            &library,
+            "empty-page",
+            r#"{"id": 5, "keywords": {}, "translations": []}"#,
+            &[("00000-00000.png", "page"), ("00000-00001.png", "")],
+        );
+        write_comic(
+            &library,
+            "badly-named",
+            r#"{"id": 6, "keywords": {}, "translations": []}"#,
+            &[("cover.png", "page")],
+        );
+        write_comic(
+            &library,
+            "not-found",
+            r#"{"id": 7, "keywords": {}, "translations": [], "found": false}"#,
+            &page,
+        );
+        write_comic(&library, "broken", "{ not json", &page);
+
+        let report = check_library(&[library.clone()], &HashMap::new());
+        assert_eq!(report.comic_count, 6);
+        assert_eq!(
+            issues_of(&report),
+            vec![
+                ("AsymmetricTranslation".to_string(), Warning, Some(1)),
+                ("ChapterGap".to_string(), Warning, Some(4)),
+                ("DuplicateId".to_string(), Error, Some(2)),
+                ("InvalidPage".to_string(), Error, Some(6)),
+                ("LoadError".to_string(), Error, None),
+                ("MissingTranslation".to_string(), Error, Some(3)),
+                ("NotFound".to_string(), Warning, None),
+                ("PageCountMismatch".to_string(), Warning, Some(4)),
+                ("PageGap".to_string(), Warning, Some(4)),
+                ("UnreadableImage".to_string(), Error, Some(5)),
+            ]
+        );
+        assert_eq!(report.error_count(), 5);
+        assert_eq!(report.warning_count(), 5);
+        assert_eq!(report.exit_code(), 1);
+        let unreadable = report
+            .issues
+            .iter()
+            .find(|issue| issue.kind == CheckIssueKind::UnreadableImage)
+            .unwrap();
+        assert_eq!(
+            unreadable.path,
+            Some(library.join("empty-page").join("00000-00001.png"))
+        );
+        assert_eq!(unreadable.message, "the page file is empty");
+
+        // a warning alone doesn't fail the check
+        let warnings = CheckReport {
+            comic_count: 1,
+            issues: report
+                .issues
+                .into_iter()
+                .filter(|issue| issue.severity == Warning)
+                .collect(),
+        };
+        assert_eq!(warnings.exit_code(), 0);
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn skipped_comics_are_reported() {
+        let library = test_library("check-lenient");
+        write_comic(
+            &library,
+            "badly-named",
+            r#"{"id": 1, "keywords": {}, "translations": []}"#,
+            &[("cover.png", "page")],
+        );
+        let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+        comic_database.load_from_dirs_lenient(&[library.clone()]);
+        let report = check_database(&comic_database);
+        assert_eq!(report.comic_count, 0);
+        assert_eq!(
+            issues_of(&report),
+            vec![("InvalidPage".to_string(), CheckSeverity::Error, Some(1))]
+        );
+
+        remove_dir_all(&library).unwrap();
+    }
+}
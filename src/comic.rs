@@ -1,31 +1,280 @@
 use serde::{Deserialize, Serialize};
 use vec_map::VecMap;
 
-use std::collections::HashMap;
-use std::ffi::{OsStr, OsString};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs::{read_dir, File};
-use std::io;
-use std::num::ParseIntError;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
 
 use thiserror::Error;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+use crate::archive;
+use crate::navigation_cache::{list_source_files, source_modified};
+use crate::scan::ScannedComic;
+use crate::{
+    read_comic_data, ArchiveError, ArchiveSource, ChapterGroup, ChapterInfo, ComicInfo,
+    ComicInfoError, ComicNavigation, DirectorySource, GetComicNavigationError, LoadProblem,
+    NavigationCache, NavigationCacheError, PageId, PageNaming, PageReader, PageSource,
+    PageSourceError, SchemaError, COMIC_INFO_FILE,
+};
+#[cfg(feature = "sqlite")]
+use crate::{Catalog, CatalogState};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Comic {
+    /// The id used internally and in the urls. It is the `id` of the data.json if there is
+    /// one, and is otherwise assigned when the comic is added to a [`ComicDatabase`].
+    #[serde(skip)]
     pub id: usize,
+    /// The `id` of the data.json
+    #[serde(rename = "id", default, skip_serializing_if = "Option::is_none")]
+    pub declared_id: Option<usize>,
+    /// A stable identifier, that can be used instead of the id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
     pub comic_name: Option<String>,
     pub description: Option<String>,
     pub keywords: HashMap<String, Vec<String>>,
-    pub translations: Vec<(String, usize)>,
+    pub translations: Vec<(String, ComicRef)>,
+    /// If false, the comic is considered as if it doesn't exist
+    #[serde(default = "found_by_default")]
     pub found: bool,
+    /// The optional title, description and date of the chapters, by chapter number
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chapters: BTreeMap<usize, ChapterInfo>,
+    /// The optional arcs or volumes grouping the chapters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chapter_groups: Vec<ChapterGroup>,
+    /// How the pages of this comic are named. If not set, the naming of the library root is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_naming: Option<PageNaming>,
+    /// The number of pages this comic should have, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+}
+
+fn found_by_default() -> bool {
+    true
+}
+
+impl Comic {
+    /// How this comic is referred to from outside: by its declared id, or by its slug if it
+    /// doesn't have one
+    pub fn reference(&self) -> ComicRef {
+        match (self.declared_id, &self.slug) {
+            (None, Some(slug)) => ComicRef::Slug(slug.clone()),
+            (declared_id, _) => ComicRef::Id(declared_id.unwrap_or(self.id)),
+        }
+    }
+
+    /// Return true if `reference` is the id or the slug of this comic
+    pub fn is_referenced_by(&self, reference: &ComicRef) -> bool {
+        match reference {
+            ComicRef::Id(id) => *id == self.id,
+            ComicRef::Slug(slug) => self.slug.as_ref() == Some(slug),
+        }
+    }
+}
+
+/// A reference to a comic, either by id or by slug. In a data.json, it is either a number
+/// or a string. When parsed from a string (like in an url), only digits are an id.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum ComicRef {
+    Id(usize),
+    Slug(String),
+}
+
+impl From<&str> for ComicRef {
+    fn from(value: &str) -> Self {
+        match value.parse::<usize>() {
+            Ok(id) => Self::Id(id),
+            Err(_) => Self::Slug(value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ComicRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id),
+            Self::Slug(slug) => write!(f, "{}", slug),
+        }
+    }
+}
+
+/// Slugs are made of ascii letters, digits, `-`, `_` and `.`, and are not only digits (so
+/// they can't be confused with an id)
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_' || char == '.')
+        && !slug.chars().all(|char| char.is_ascii_digit())
+}
+
+/// A comic loaded in a [`ComicDatabase`], with the place it was loaded from
+#[derive(Debug, Clone)]
+pub struct ComicEntry {
+    /// The folder of this comic, or the archive if it is a standalone archive
+    pub path: PathBuf,
+    /// Where the pages are stored
+    pub pages: Arc<dyn PageSource>,
+    /// The index of the library root this comic was found in (see [`ComicDatabase::roots`])
+    pub root: usize,
+    /// The modification time of the page source when the comic was loaded.
+    /// It change when pages are added or removed.
+    pub modified: Option<SystemTime>,
+    pub comic: Comic,
+}
+
+/// The difference between two versions of a [`ComicDatabase`]
+#[derive(Debug, Clone, Default)]
+pub struct LibraryChanges {
+    pub added: Vec<usize>,
+    /// Comics whose data, folder or pages changed
+    pub updated: Vec<usize>,
+    pub removed: Vec<usize>,
+}
+
+impl LibraryChanges {
+    /// The ids of the comics that were added, updated or removed
+    pub fn changed_ids(&self) -> Vec<usize> {
+        let mut changed_ids = self.added.clone();
+        changed_ids.extend(&self.updated);
+        changed_ids.extend(&self.removed);
+        changed_ids
+    }
+}
+
+/// What changed in a comic after [`ComicDatabase::rescan_comic`]
+#[derive(Debug, Clone, Default)]
+pub struct ComicChanges {
+    pub comic_id: usize,
+    /// The comic no longer exist (its data.json was removed or has found set to false)
+    pub removed: bool,
+    /// The id of the comic was changed in its data.json
+    pub new_id: Option<usize>,
+    /// Something changed in the data.json
+    pub data_changed: bool,
+    /// (category, keyword) pairs
+    pub keywords_added: Vec<(String, String)>,
+    pub keywords_removed: Vec<(String, String)>,
+    pub pages_added: Vec<PageId>,
+    pub pages_removed: Vec<PageId>,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// What to do when two comics share the same id
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateIdPolicy {
+    /// Stop the loading with a [`ComicDatabaseLoadError::IdCollision`]
+    Fail,
+    /// Ignore every comic that use this id, including the one loaded first
+    Skip,
+    /// Keep the comic loaded first, and ignore the following one
+    KeepFirst,
+}
+
+impl FromStr for DuplicateIdPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "skip" => Ok(Self::Skip),
+            "keep-first" => Ok(Self::KeepFirst),
+            _ => Err(format!(
+                "unknown duplicate id policy {:?} (expected fail, skip or keep-first)",
+                s
+            )),
+        }
+    }
+}
+
+/// Two comics that were found with the same id or slug
+#[derive(Debug, Clone)]
+pub struct DuplicateComicId {
+    pub id: ComicRef,
+    /// The folder of the comic that was loaded first
+    pub first_path: PathBuf,
+    /// The folder of the comic that was loaded after
+    pub second_path: PathBuf,
+}
+
+impl fmt::Display for DuplicateComicId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the comics at {:?} and {:?} share the id {}",
+            self.first_path, self.second_path, self.id
+        )
+    }
+}
+
+/// A comic, and where its pages are stored
+type ComicLocation = (Comic, Arc<dyn PageSource>);
+
+/// A [`ComicDatabase`] that can be replaced while the server is running
+pub type SharedComicDatabase = Arc<RwLock<ComicDatabase>>;
+
+/// Reload a shared database. It is only locked for writing once the new database is loaded,
+/// so it can still be read in the meantime.
+///
+/// If the database is changed while the new one is loading (by a rescan of a comic, or another
+/// reload), the new database may be outdated, so it is loaded again.
+pub fn reload_shared_database(
+    comic_database: &SharedComicDatabase,
+    lenient: bool,
+) -> Result<LibraryChanges, ComicDatabaseLoadError> {
+    loop {
+        let (mut new_database, generation) = {
+            let comic_database = comic_database.read().unwrap();
+            (comic_database.reload(lenient)?, comic_database.generation)
+        };
+        let mut comic_database = comic_database.write().unwrap();
+        if comic_database.generation != generation {
+            continue;
+        };
+        let changes = new_database.changes_since(&comic_database);
+        new_database.generation = generation + 1;
+        *comic_database = new_database;
+        return Ok(changes);
+    }
 }
 
-#[derive(Default)]
 pub struct ComicDatabase {
-    comics: VecMap<(PathBuf, Comic)>,
+    duplicate_id_policy: DuplicateIdPolicy,
+    duplicate_ids: Vec<DuplicateComicId>,
+    load_problems: Vec<LoadProblem>,
+    not_found_paths: Vec<PathBuf>,
+    roots: Vec<PathBuf>,
+    page_namings: HashMap<PathBuf, PageNaming>,
+    comics: VecMap<ComicEntry>,
+    slugs: HashMap<String, usize>,
+    /// The id to give to the comic without declared id at this path, if it is free
+    preferred_ids: HashMap<PathBuf, usize>,
     keywords: HashMap<String, HashMap<String, Vec<usize>>>,
-    navigation_cache: Mutex<VecMap<Vec<Vec<Option<PathBuf>>>>>,
+    navigation_cache: Mutex<VecMap<Arc<ComicNavigation>>>,
+    /// The files of the page sources saved between restarts, shared with the reloaded databases
+    listing_cache: Option<Arc<NavigationCache>>,
+    /// The SQLite copy of the library the keywords and pages are read from, shared with the
+    /// reloaded databases
+    #[cfg(feature = "sqlite")]
+    catalog: Option<Arc<Catalog>>,
+    /// Incremented each time the comics are rescanned or reloaded
+    generation: usize,
 }
 
 #[derive(Error, Debug)]
@@ -37,211 +286,1161 @@ pub enum ComicDatabaseLoadError {
     #[error("failed to open file at {1}")]
     CantOpenFile(#[source] io::Error, PathBuf),
     #[error("failed to deserialize a comic data file at {1}")]
-    CantDeserializeComic(#[source] serde_json::Error, PathBuf),
+    CantDeserializeComic(#[source] SchemaError, PathBuf),
+    #[error("failed to read the ComicInfo.xml at {1}")]
+    CantReadComicInfo(#[source] ComicInfoError, PathBuf),
+    #[error("the comic folder {0} contain more than one archive")]
+    MultipleArchives(PathBuf),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error("the comic at {0} has neither an id nor a slug")]
+    NoIdentifier(PathBuf),
+    #[error("the slug {0:?} of the comic at {1} is invalid (it should only contain ascii letters, digits, '-', '_' and '.', and not only digits)")]
+    InvalidSlug(String, PathBuf),
+    #[error("the comic at {new_path} has the id {id}, which is already used by the comic at {existing_path}")]
+    IdCollision {
+        id: ComicRef,
+        existing_path: PathBuf,
+        new_path: PathBuf,
+    },
 }
 
 #[derive(Error, Debug)]
-pub enum GetComicNavigationError {
-    #[error("failed to list sub content of {1}")]
-    CantReadDirectory(#[source] io::Error, PathBuf),
-    #[error("failed to read an entry of the content of the director {1}")]
-    CantReadDirEntry(#[source] io::Error, PathBuf),
+pub enum RescanComicError {
     #[error("this comic ({0}) doesn't exist")]
     ComicDontExist(usize),
-    #[error("the file at {0} doesn't have a file name, but one is required")]
-    FileWithNoName(PathBuf),
-    #[error("can't convert an OsStr to a String")]
-    CantConvertOsToString(OsString),
-    #[error("the file at {0} doesn't have a stem (file name without extension)")]
-    FileWithNoStem(PathBuf),
-    #[error("can't get the {0} value of {1} when splited by '-' (count start at 0)")]
-    CantGetSplitedDash(u32, String),
-    #[error("can't convert the value {1} from {2} to an usize")]
-    CantConvertStringFromPathToInt(ParseIntError, String, PathBuf),
+    #[error("can't load the comic again")]
+    Load(#[from] ComicDatabaseLoadError),
+    #[error("can't list the pages of the comic again")]
+    Navigation(#[from] GetComicNavigationError),
+}
+
+#[derive(Error, Debug)]
+pub enum OpenPageError {
+    #[error("can't list the pages of the comic")]
+    Navigation(#[from] GetComicNavigationError),
+    #[error("the {0} doesn't exist")]
+    PageDontExist(PageId),
+    #[error("the {0} doesn't have a document {1}")]
+    DocumentDontExist(PageId, usize),
+    #[error("can't open the page")]
+    CantOpenPage(#[from] PageSourceError),
 }
 
 impl ComicDatabase {
-    pub fn add_comic(&mut self, path: PathBuf, comic: Comic) {
-        for (keyword_category, values) in &comic.keywords {
-            if let Some(keyword_hashmap) = self.keywords.get_mut(keyword_category) {
-                for section_name in values {
-                    if let Some(section_vec) = keyword_hashmap.get_mut(section_name) {
-                        section_vec.push(comic.id);
-                    } else {
-                        keyword_hashmap.insert(section_name.to_string(), vec![comic.id]);
-                    }
+    pub fn new(duplicate_id_policy: DuplicateIdPolicy) -> Self {
+        Self {
+            duplicate_id_policy,
+            duplicate_ids: Vec::new(),
+            load_problems: Vec::new(),
+            not_found_paths: Vec::new(),
+            roots: Vec::new(),
+            page_namings: HashMap::new(),
+            comics: VecMap::new(),
+            slugs: HashMap::new(),
+            preferred_ids: HashMap::new(),
+            keywords: HashMap::new(),
+            navigation_cache: Mutex::new(VecMap::new()),
+            listing_cache: None,
+            #[cfg(feature = "sqlite")]
+            catalog: None,
+            generation: 0,
+        }
+    }
+
+    /// Use this cache for the files of the page sources, instead of listing them again when
+    /// they didn't change. It is kept by [`ComicDatabase::reload`].
+    pub fn set_navigation_cache(&mut self, cache: Arc<NavigationCache>) {
+        self.listing_cache = Some(cache);
+    }
+
+    /// Save the navigation cache, if there is one, forgetting the comics that are no longer in
+    /// this database
+    pub fn save_navigation_cache(&self) -> Result<(), NavigationCacheError> {
+        if let Some(cache) = &self.listing_cache {
+            let locations = self
+                .comics
+                .values()
+                .map(|entry| entry.pages.location())
+                .collect();
+            cache.retain(&locations);
+            cache.save()?;
+        };
+        Ok(())
+    }
+
+    /// Read the keywords and the pages of the comics from this catalog, instead of keeping them
+    /// in memory. It should be set before loading the library, and filled with
+    /// [`sync_catalog`](crate::sync_catalog) once it is loaded. It is kept by
+    /// [`ComicDatabase::reload`].
+    #[cfg(feature = "sqlite")]
+    pub fn set_catalog(&mut self, catalog: Arc<Catalog>) {
+        self.catalog = Some(catalog);
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn catalog(&self) -> Option<Arc<Catalog>> {
+        self.catalog.clone()
+    }
+
+    /// Stop keeping the navigation of these comics in memory, once they are in the catalog
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn forget_navigations(&self, comic_ids: &[usize]) {
+        let mut navigation_cache = self.navigation_cache.lock().unwrap();
+        for comic_id in comic_ids {
+            navigation_cache.remove(*comic_id);
+        }
+    }
+
+    #[cfg(all(test, feature = "sqlite"))]
+    pub(crate) fn is_navigation_cached(&self, id: usize) -> bool {
+        self.navigation_cache.lock().unwrap().contains_key(id)
+    }
+
+    /// True if the keywords are indexed in memory, as there is no catalog to read them from
+    fn keeps_keyword_index(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        {
+            self.catalog.is_none()
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            true
+        }
+    }
+
+    /// The catalog, if there is one and it contain this library
+    #[cfg(feature = "sqlite")]
+    fn synced_catalog(&self) -> Option<&Catalog> {
+        self.catalog
+            .as_deref()
+            .filter(|catalog| catalog.is_synced())
+    }
+
+    /// Set how the pages of the comics of the library root at `root` are named, when their
+    /// data.json doesn't say otherwise. It should be set before loading this root.
+    pub fn set_page_naming(&mut self, root: PathBuf, naming: PageNaming) {
+        self.page_namings.insert(root, naming);
+    }
+
+    /// How the pages of this comic are named
+    pub fn get_page_naming(&self, id: usize) -> Option<&PageNaming> {
+        let entry = self.comics.get(id)?;
+        Some(self.page_naming_of(&entry.comic, entry.root))
+    }
+
+    /// How the pages of a comic found in the library root with the index `root` are named
+    fn page_naming_of<'a>(&'a self, comic: &'a Comic, root: usize) -> &'a PageNaming {
+        match &comic.page_naming {
+            Some(naming) => naming,
+            None => self
+                .roots
+                .get(root)
+                .and_then(|root| self.page_namings.get(root))
+                .unwrap_or(&PageNaming::Standard),
+        }
+    }
+
+    /// Add a comic found in the library root with the index `root`.
+    ///
+    /// If another comic with the same id or slug is already present, the [`DuplicateIdPolicy`]
+    /// of this database decide whether an error is returned, or the duplicate is recorded
+    /// (see [`ComicDatabase::duplicate_ids`]) and one or both comic are ignored.
+    ///
+    /// Comics without an id in their data.json keep the id they had before a reload or a
+    /// rescan if possible, and otherwise get the id returned by [`ComicDatabase::unused_id`].
+    /// If a comic declaring this id is added later, the comic without declared id is moved
+    /// to another id.
+    ///
+    /// Return the id of the comic, or `None` if it was ignored as a duplicate.
+    pub fn add_comic(
+        &mut self,
+        path: PathBuf,
+        pages: Arc<dyn PageSource>,
+        root: usize,
+        comic: Comic,
+    ) -> Result<Option<usize>, ComicDatabaseLoadError> {
+        let modified = source_modified(self.listing_cache.as_deref(), &*pages);
+        self.add_entry(ComicEntry {
+            path,
+            pages,
+            root,
+            modified,
+            comic,
+        })
+    }
+
+    /// Add a comic, as described in [`ComicDatabase::add_comic`]. The id of the entry is set
+    /// by this function.
+    fn add_entry(
+        &mut self,
+        mut entry: ComicEntry,
+    ) -> Result<Option<usize>, ComicDatabaseLoadError> {
+        let comic = &entry.comic;
+        if let Some(slug) = &comic.slug {
+            if !is_valid_slug(slug) {
+                return Err(ComicDatabaseLoadError::InvalidSlug(
+                    slug.clone(),
+                    entry.path,
+                ));
+            };
+        };
+        if comic.declared_id.is_none() && comic.slug.is_none() {
+            return Err(ComicDatabaseLoadError::NoIdentifier(entry.path));
+        };
+
+        let existing = comic
+            .declared_id
+            .filter(|id| {
+                matches!(self.comics.get(*id), Some(entry) if entry.comic.declared_id.is_some())
+            })
+            .map(|id| (ComicRef::Id(id), id));
+        let existing = existing.or_else(|| {
+            let slug = comic.slug.as_ref()?;
+            let id = self.slugs.get(slug)?;
+            Some((ComicRef::Slug(slug.clone()), *id))
+        });
+
+        if let Some((reference, existing_id)) = existing {
+            let existing_path = self.comics[existing_id].path.clone();
+            match self.duplicate_id_policy {
+                DuplicateIdPolicy::Fail => {
+                    return Err(ComicDatabaseLoadError::IdCollision {
+                        id: reference,
+                        existing_path,
+                        new_path: entry.path,
+                    })
                 }
-            } else {
-                let mut new_category_map: HashMap<String, Vec<usize>> = HashMap::new();
-                for section_name in values {
-                    if let Some(section_vec) = new_category_map.get_mut(section_name) {
-                        section_vec.push(comic.id);
-                    } else {
-                        new_category_map.insert(section_name.to_string(), vec![comic.id]);
-                    }
+                DuplicateIdPolicy::KeepFirst => (),
+                DuplicateIdPolicy::Skip => {
+                    self.remove_comic(existing_id);
                 }
-                self.keywords
-                    .insert(keyword_category.clone(), new_category_map);
+            };
+            self.duplicate_ids.push(DuplicateComicId {
+                id: reference,
+                first_path: existing_path,
+                second_path: entry.path,
+            });
+            return Ok(None);
+        };
+
+        // with the skip policy, the first comic has already been removed
+        if let Some(previous) = self
+            .duplicate_ids
+            .iter()
+            .find(|duplicate| match &duplicate.id {
+                ComicRef::Id(id) => comic.declared_id == Some(*id),
+                ComicRef::Slug(slug) => comic.slug.as_ref() == Some(slug),
+            })
+        {
+            let duplicate = DuplicateComicId {
+                id: previous.id.clone(),
+                first_path: previous.first_path.clone(),
+                second_path: entry.path,
+            };
+            self.duplicate_ids.push(duplicate);
+            return Ok(None);
+        };
+
+        // the comic currently using the declared id, if any, doesn't have a declared id
+        let moved_entry = comic.declared_id.and_then(|id| self.remove_comic(id));
+        entry.comic.id = match entry.comic.declared_id {
+            Some(id) => id,
+            None => match self.preferred_ids.get(&entry.path) {
+                Some(id) if !self.comics.contains_key(*id) => *id,
+                _ => self.unused_id(),
+            },
+        };
+        let id = entry.comic.id;
+        self.insert_entry(entry);
+
+        if let Some(mut moved_entry) = moved_entry {
+            moved_entry.comic.id = self.unused_id();
+            self.insert_entry(moved_entry);
+        };
+        Ok(Some(id))
+    }
+
+    /// Add an entry whose id is already set, and index its slug, and its keywords if there is
+    /// no catalog
+    fn insert_entry(&mut self, entry: ComicEntry) {
+        let comic = &entry.comic;
+        if self.keeps_keyword_index() {
+            index_keywords(&mut self.keywords, comic);
+        };
+        if let Some(slug) = &comic.slug {
+            self.slugs.insert(slug.clone(), comic.id);
+        };
+        self.comics.insert(comic.id, entry);
+    }
+
+    /// Remove the comic with this id, and all references to it in the keyword index and the
+    /// navigation cache
+    pub fn remove_comic(&mut self, id: usize) -> Option<ComicEntry> {
+        let removed = self.comics.remove(id)?;
+        if let Some(slug) = &removed.comic.slug {
+            self.slugs.remove(slug);
+        };
+        for keyword_category in self.keywords.values_mut() {
+            for section_vec in keyword_category.values_mut() {
+                section_vec.retain(|comic_id| *comic_id != id);
             }
+            keyword_category.retain(|_, section_vec| !section_vec.is_empty());
+        }
+        self.navigation_cache.lock().unwrap().remove(id);
+        Some(removed)
+    }
+
+    /// The comics that were found with an already used id, and were ignored
+    pub fn duplicate_ids(&self) -> &[DuplicateComicId] {
+        &self.duplicate_ids
+    }
+
+    /// Load every library root in order, merging them in this database
+    pub fn load_from_dirs(&mut self, folders: &[PathBuf]) -> Result<(), ComicDatabaseLoadError> {
+        for folder in folders {
+            self.load_from_dir(folder.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Load every library root in order, without stopping at the first problem.
+    ///
+    /// Comics that can't be loaded, or whose navigation can't be computed, are skipped, and the
+    /// problems are recorded (see [`ComicDatabase::load_problems`]).
+    pub fn load_from_dirs_lenient(&mut self, folders: &[PathBuf]) {
+        self.load_from_dirs_recording_problems(folders);
+        self.skip_comics_without_navigation();
+    }
+
+    /// Load every library root in order, recording the errors instead of stopping. Unlike
+    /// [`ComicDatabase::load_from_dirs_lenient`], the comics whose navigation can't be computed
+    /// are kept.
+    pub(crate) fn load_from_dirs_recording_problems(&mut self, folders: &[PathBuf]) {
+        let mut problems = Vec::new();
+        for folder in folders {
+            // the error handler never return an error, so neither does this function
+            let _ = self.load_from_dir_with(folder.clone(), &mut |err| {
+                problems.push(LoadProblem::Load(err));
+                Ok(())
+            });
+        }
+        self.load_problems.extend(problems);
+    }
+
+    /// Remove the comics whose navigation can't be computed, and record why
+    fn skip_comics_without_navigation(&mut self) {
+        let comic_ids: Vec<usize> = self.comics.keys().collect();
+        for comic_id in comic_ids {
+            if let Err(err) = self.get_comic_navigation(comic_id) {
+                self.load_problems
+                    .push(LoadProblem::Navigation(comic_id, err));
+                self.remove_comic(comic_id);
+            };
+        }
+    }
+
+    /// Load all the comics that are a subfolder of `folder`, and register it as a new library root
+    pub fn load_from_dir(&mut self, folder: PathBuf) -> Result<(), ComicDatabaseLoadError> {
+        self.load_from_dir_with(folder, &mut Err)
+    }
+
+    /// Load again all the library roots of this database, returning the new database.
+    ///
+    /// This database is left untouched, so it can still be used while the new one is loading.
+    /// The navigation of the comics whose folder and data didn't change is reused instead of
+    /// being read again.
+    pub fn reload(&self, lenient: bool) -> Result<Self, ComicDatabaseLoadError> {
+        let mut new_database = Self::new(self.duplicate_id_policy);
+        new_database.page_namings = self.page_namings.clone();
+        new_database.listing_cache = self.listing_cache.clone();
+        #[cfg(feature = "sqlite")]
+        {
+            new_database.catalog = self.catalog.clone();
+        }
+        new_database.preferred_ids = self
+            .comics
+            .iter()
+            .filter(|(_, entry)| entry.comic.declared_id.is_none())
+            .map(|(comic_id, entry)| (entry.path.clone(), comic_id))
+            .collect();
+        if lenient {
+            new_database.load_from_dirs_recording_problems(&self.roots);
+        } else {
+            new_database.load_from_dirs(&self.roots)?;
         };
 
-        if !self.keywords.contains_key("translation") {
-            self.keywords.insert("translation".into(), HashMap::new());
+        {
+            let old_cache = self.navigation_cache.lock().unwrap();
+            let mut new_cache = new_database.navigation_cache.lock().unwrap();
+            for (comic_id, navigation) in old_cache.iter() {
+                if let (Some(old_entry), Some(new_entry)) =
+                    (self.comics.get(comic_id), new_database.comics.get(comic_id))
+                {
+                    if old_entry.path == new_entry.path
+                        && old_entry.pages.location() == new_entry.pages.location()
+                        && old_entry.modified.is_some()
+                        && old_entry.modified == new_entry.modified
+                        && old_entry.comic == new_entry.comic
+                    {
+                        new_cache.insert(comic_id, navigation.clone());
+                    };
+                };
+            }
+        }
+
+        if lenient {
+            new_database.skip_comics_without_navigation();
         };
-        let trans_hashmap = self.keywords.get_mut("translation").unwrap(); //TODO: check for a get_key_or_create
-        for (trans_lang, trans_comic_id) in &comic.translations {
-            if *trans_comic_id == comic.id {
-                if let Some(lang_vec) = trans_hashmap.get_mut(trans_lang) {
-                    lang_vec.push(comic.id);
-                } else {
-                    trans_hashmap.insert(trans_lang.to_string(), vec![comic.id]);
+        Ok(new_database)
+    }
+
+    /// List the comics that were added, updated or removed in this database compared to `old`
+    pub fn changes_since(&self, old: &ComicDatabase) -> LibraryChanges {
+        let mut changes = LibraryChanges::default();
+        for (comic_id, entry) in self.comics.iter() {
+            match old.comics.get(comic_id) {
+                None => changes.added.push(comic_id),
+                Some(old_entry) => {
+                    if old_entry.path != entry.path
+                        || old_entry.pages.location() != entry.pages.location()
+                        || old_entry.modified != entry.modified
+                        || old_entry.comic != entry.comic
+                    {
+                        changes.updated.push(comic_id)
+                    };
+                }
+            };
+        }
+        for comic_id in old.comics.keys() {
+            if !self.comics.contains_key(comic_id) {
+                changes.removed.push(comic_id);
+            };
+        }
+        changes
+    }
+
+    /// Load all the comics that are a subfolder of `folder`. Each error is passed to `on_error`,
+    /// and the loading stop only if it return an error.
+    fn load_from_dir_with(
+        &mut self,
+        folder: PathBuf,
+        on_error: &mut dyn FnMut(ComicDatabaseLoadError) -> Result<(), ComicDatabaseLoadError>,
+    ) -> Result<(), ComicDatabaseLoadError> {
+        let root = self.register_root(folder.clone());
+
+        let paths = match read_dir(&folder) {
+            Ok(paths) => paths,
+            Err(err) => return on_error(ComicDatabaseLoadError::CantReadDirectory(err, folder)),
+        };
+        for path in paths {
+            let folder_path = match path {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    on_error(ComicDatabaseLoadError::CantReadDirEntry(
+                        err,
+                        folder.clone(),
+                    ))?;
+                    continue;
                 }
+            };
+            if let Err(err) = self.load_comic_folder(folder_path, root) {
+                on_error(err)?;
+            };
+        }
+        Ok(())
+    }
+
+    /// Register `folder` as a new library root, and return its index
+    pub(crate) fn register_root(&mut self, folder: PathBuf) -> usize {
+        self.roots.push(folder);
+        self.roots.len() - 1
+    }
+
+    pub(crate) fn record_load_problem(&mut self, problem: LoadProblem) {
+        self.load_problems.push(problem);
+    }
+
+    /// The cache of the files of the page sources, if there is one
+    pub(crate) fn listing_cache(&self) -> Option<Arc<NavigationCache>> {
+        self.listing_cache.clone()
+    }
+
+    /// Add a comic read by [`scan_library`](crate::scan_library). Its navigation is computed
+    /// from the files listed while scanning, instead of being listed again.
+    ///
+    /// If `lenient` is true, the errors are recorded instead of being returned, and a comic
+    /// whose navigation can't be computed is skipped, like with
+    /// [`ComicDatabase::load_from_dirs_lenient`].
+    pub(crate) fn add_scanned_comic(
+        &mut self,
+        scanned: ScannedComic,
+        lenient: bool,
+    ) -> Result<(), ComicDatabaseLoadError> {
+        match self.try_add_scanned_comic(scanned, lenient) {
+            Err(err) if lenient => {
+                self.load_problems.push(LoadProblem::Load(err));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn try_add_scanned_comic(
+        &mut self,
+        scanned: ScannedComic,
+        lenient: bool,
+    ) -> Result<(), ComicDatabaseLoadError> {
+        let content = match scanned.content? {
+            Some(content) => content,
+            None => return Ok(()),
+        };
+        let files = match content.files {
+            Some(files) => files,
+            None => {
+                self.not_found_paths.push(scanned.path);
+                return Ok(());
             }
         };
-        self.comics.insert(comic.id, (path, comic));
+        let id = match self.add_entry(ComicEntry {
+            path: scanned.path,
+            pages: content.pages,
+            root: scanned.root,
+            modified: content.modified,
+            comic: content.comic,
+        })? {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let entry = &self.comics[id];
+        // the comic was just added, so it has a naming
+        let naming = self.get_page_naming(id).unwrap();
+        let navigation = files
+            .map_err(GetComicNavigationError::from)
+            .and_then(|files| {
+                ComicNavigation::from_files(&*entry.pages, files, &entry.comic, naming)
+            });
+        match navigation {
+            Ok(navigation) => {
+                self.navigation_cache
+                    .lock()
+                    .unwrap()
+                    .insert(id, Arc::new(navigation));
+            }
+            Err(err) if lenient => {
+                self.load_problems.push(LoadProblem::Navigation(id, err));
+                self.remove_comic(id);
+            }
+            // the error is returned again when the navigation is requested
+            Err(_) => (),
+        };
+        Ok(())
     }
 
-    pub fn load_from_dir(&mut self, folder: PathBuf) -> Result<(), ComicDatabaseLoadError> {
-        let paths = read_dir(&folder)
-            .map_err(|err| ComicDatabaseLoadError::CantReadDirectory(err, folder.clone()))?;
-        for path in paths {
-            let mut data_path = path
-                .map_err(|err| ComicDatabaseLoadError::CantReadDirEntry(err, folder.clone()))?
-                .path();
-            let folder_path = data_path.clone();
-            data_path.push("data.json");
-
-            if data_path.exists() {
-                println!("subfolder name: {:?}", data_path);
-                let data_file = File::open(&data_path)
-                    .map_err(|err| ComicDatabaseLoadError::CantOpenFile(err, data_path.clone()))?;
-                let comic: Comic = serde_json::from_reader(data_file).map_err(|err| {
-                    ComicDatabaseLoadError::CantDeserializeComic(err, data_path.clone())
-                })?;
-
-                if comic.found {
-                    self.add_comic(folder_path, comic);
+    /// Read the data.json of the comic in `folder_path`, or its ComicInfo.xml if it doesn't
+    /// have one. Return `None` if there is neither.
+    fn read_comic_folder(folder_path: &Path) -> Result<Option<Comic>, ComicDatabaseLoadError> {
+        let data_path = folder_path.join("data.json");
+        let info_path = folder_path.join(COMIC_INFO_FILE);
+
+        if data_path.exists() {
+            let data_file = File::open(&data_path)
+                .map_err(|err| ComicDatabaseLoadError::CantOpenFile(err, data_path.clone()))?;
+            let comic = read_comic_data(data_file).map_err(|err| {
+                ComicDatabaseLoadError::CantDeserializeComic(err, data_path.clone())
+            })?;
+            Ok(Some(comic))
+        } else if info_path.exists() {
+            let info_file = File::open(&info_path)
+                .map_err(|err| ComicDatabaseLoadError::CantOpenFile(err, info_path.clone()))?;
+            let info = ComicInfo::read(BufReader::new(info_file))
+                .map_err(|err| ComicDatabaseLoadError::CantReadComicInfo(err, info_path.clone()))?;
+            let folder_name = folder_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            Ok(Some(info.to_comic(&folder_name)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Find where the pages of the comic in this folder are stored: either in an archive in
+    /// this folder, or as files directly in the folder. Only the names of the files directly in
+    /// the folder are read, the pages are listed later (see [`NavigationCache`]).
+    fn find_page_source_in(
+        folder_path: &Path,
+    ) -> Result<Arc<dyn PageSource>, ComicDatabaseLoadError> {
+        let entries = read_dir(folder_path).map_err(|err| {
+            ComicDatabaseLoadError::CantReadDirectory(err, folder_path.to_path_buf())
+        })?;
+        let mut archive_path = None;
+        for entry in entries {
+            let entry = entry.map_err(|err| {
+                ComicDatabaseLoadError::CantReadDirEntry(err, folder_path.to_path_buf())
+            })?;
+            let path = entry.path();
+            if archive::is_archive(&path) {
+                if archive_path.is_some() {
+                    return Err(ComicDatabaseLoadError::MultipleArchives(
+                        folder_path.to_path_buf(),
+                    ));
                 };
+                archive_path = Some(path);
             };
         }
+        Ok(match archive_path {
+            Some(archive_path) => Arc::new(ArchiveSource::new(archive_path)),
+            None => Arc::new(DirectorySource::new(folder_path.to_path_buf())),
+        })
+    }
+
+    /// Read the comic stored at `path`, which is either a folder with a data.json file (with the
+    /// pages in the folder, or in an archive next to the data.json), or an archive with a
+    /// data.json inside. A ComicInfo.xml is used instead of a missing data.json.
+    /// Return the comic and the source of its pages.
+    pub(crate) fn read_comic_location(
+        path: &Path,
+    ) -> Result<Option<ComicLocation>, ComicDatabaseLoadError> {
+        if archive::is_archive(path) {
+            let pages: Arc<dyn PageSource> = Arc::new(ArchiveSource::new(path.to_path_buf()));
+            let comic = match archive::read_data_json(path)? {
+                Some(comic) => Some(comic),
+                None => archive::read_comic_info(path)?.map(|info| {
+                    let archive_name = path.file_stem().unwrap_or_default().to_string_lossy();
+                    info.to_comic(&archive_name)
+                }),
+            };
+            return Ok(comic.map(|comic| (comic, pages)));
+        };
+        match Self::read_comic_folder(path)? {
+            Some(comic) => Ok(Some((comic, Self::find_page_source_in(path)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the comic in `folder_path`, if it contain a data.json file
+    fn load_comic_folder(
+        &mut self,
+        folder_path: PathBuf,
+        root: usize,
+    ) -> Result<(), ComicDatabaseLoadError> {
+        if let Some((comic, pages)) = Self::read_comic_location(&folder_path)? {
+            if comic.found {
+                self.add_comic(folder_path, pages, root, comic)?;
+            } else {
+                self.not_found_paths.push(folder_path);
+            };
+        };
         Ok(())
     }
 
-    pub fn comics(&self) -> &VecMap<(PathBuf, Comic)> {
+    /// Read again the data.json and the pages of this comic, and update it in this database.
+    /// If an error is returned, the comic is left as it was.
+    pub fn rescan_comic(&mut self, id: usize) -> Result<ComicChanges, RescanComicError> {
+        let old_entry = self
+            .comics
+            .get(id)
+            .cloned()
+            .ok_or(RescanComicError::ComicDontExist(id))?;
+        let old_navigation = self.get_comic_navigation(id).ok();
+        self.generation += 1;
+
+        let mut changes = ComicChanges {
+            comic_id: id,
+            ..ComicChanges::default()
+        };
+
+        let (new_comic, new_pages) = match Self::read_comic_location(&old_entry.path)? {
+            Some((new_comic, new_pages)) if new_comic.found => (new_comic, new_pages),
+            Some(_) => {
+                self.remove_comic(id);
+                self.not_found_paths.push(old_entry.path);
+                changes.removed = true;
+                return Ok(changes);
+            }
+            None => {
+                self.remove_comic(id);
+                changes.removed = true;
+                return Ok(changes);
+            }
+        };
+        // the pages are listed before the old comic is removed, so it is kept if they are invalid
+        let new_navigation = Arc::new(self.read_navigation(
+            &*new_pages,
+            &new_comic,
+            old_entry.root,
+            source_modified(self.listing_cache.as_deref(), &*new_pages),
+        )?);
+        self.remove_comic(id);
+
+        let keyword_set = |comic: &Comic| -> HashSet<(String, String)> {
+            comic
+                .keywords
+                .iter()
+                .flat_map(|(category, values)| {
+                    values
+                        .iter()
+                        .map(move |value| (category.clone(), value.clone()))
+                })
+                .collect()
+        };
+        let old_keywords = keyword_set(&old_entry.comic);
+        let new_keywords = keyword_set(&new_comic);
+        changes.keywords_added = new_keywords.difference(&old_keywords).cloned().collect();
+        changes.keywords_removed = old_keywords.difference(&new_keywords).cloned().collect();
+        // the internal id isn't read from the data.json
+        changes.data_changed = Comic {
+            id: old_entry.comic.id,
+            ..new_comic.clone()
+        } != old_entry.comic;
+
+        self.preferred_ids.insert(old_entry.path.clone(), id);
+        let added = self.add_comic(
+            old_entry.path.clone(),
+            new_pages,
+            old_entry.root,
+            new_comic,
+        );
+        let new_id = match added {
+            Ok(Some(new_id)) => new_id,
+            // it now use the id or slug of another comic
+            Ok(None) => {
+                changes.removed = true;
+                return Ok(changes);
+            }
+            Err(err) => {
+                self.insert_entry(old_entry);
+                if let Some(old_navigation) = old_navigation {
+                    self.navigation_cache
+                        .lock()
+                        .unwrap()
+                        .insert(id, old_navigation);
+                };
+                return Err(err.into());
+            }
+        };
+        if new_id != id {
+            changes.new_id = Some(new_id);
+        };
+        self.navigation_cache
+            .lock()
+            .unwrap()
+            .insert(new_id, new_navigation.clone());
+
+        let page_set = |navigation: &ComicNavigation| -> HashSet<PageId> {
+            navigation.pages().map(|(page_id, _)| page_id).collect()
+        };
+        let old_pages = old_navigation
+            .as_deref()
+            .map(&page_set)
+            .unwrap_or_default();
+        let new_pages = page_set(&new_navigation);
+        changes.pages_added = new_pages.difference(&old_pages).cloned().collect();
+        changes.pages_added.sort_unstable();
+        changes.pages_removed = old_pages.difference(&new_pages).cloned().collect();
+        changes.pages_removed.sort_unstable();
+
+        Ok(changes)
+    }
+
+    /// The folders of the comics that were ignored because their `found` value is false
+    pub fn not_found_paths(&self) -> &[PathBuf] {
+        &self.not_found_paths
+    }
+
+    /// The problems encountered while loading the library in lenient mode
+    pub fn load_problems(&self) -> &[LoadProblem] {
+        &self.load_problems
+    }
+
+    pub fn comics(&self) -> &VecMap<ComicEntry> {
         &self.comics
     }
 
+    /// An id that isn't used by any comic of this database, including the ignored duplicates.
+    /// It is greater than all the used ids, so the id of a removed comic isn't reused.
+    pub fn unused_id(&self) -> usize {
+        self.comics
+            .keys()
+            .chain(
+                self.duplicate_ids
+                    .iter()
+                    .filter_map(|duplicate| match duplicate.id {
+                        ComicRef::Id(id) => Some(id),
+                        ComicRef::Slug(_) => None,
+                    }),
+            )
+            .max()
+            .map_or(0, |max_id| max_id + 1)
+    }
+
     pub fn get_comic(&self, id: usize) -> Option<&Comic> {
-        self.comics.get(id).map(|pair| &pair.1)
+        self.comics.get(id).map(|entry| &entry.comic)
     }
 
-    //TODO: get the section name
+    /// The id of the comic with this id or slug
+    pub fn resolve(&self, reference: &ComicRef) -> Option<usize> {
+        match reference {
+            ComicRef::Id(id) if self.comics.contains_key(*id) => Some(*id),
+            ComicRef::Id(_) => None,
+            ComicRef::Slug(slug) => self.slugs.get(slug).copied(),
+        }
+    }
+
+    /// The comic with this id or slug
+    pub fn get_comic_by_ref(&self, reference: &ComicRef) -> Option<&Comic> {
+        self.resolve(reference).and_then(|id| self.get_comic(id))
+    }
+
+    /// The library roots, in the order they were loaded
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// The library root the comic with this id was loaded from
+    pub fn get_comic_root(&self, id: usize) -> Option<&PathBuf> {
+        self.comics
+            .get(id)
+            .and_then(|entry| self.roots.get(entry.root))
+    }
+
+    /// The chapters and pages of a comic. It is computed from the pages in the catalog if they
+    /// are still valid, and computed once, then cached, otherwise.
     pub fn get_comic_navigation(
         &self,
         id: usize,
-    ) -> Result<Vec<Vec<Option<PathBuf>>>, GetComicNavigationError> {
+    ) -> Result<Arc<ComicNavigation>, GetComicNavigationError> {
         let mut navigation_cache_lock = self.navigation_cache.lock().unwrap();
 
         if let Some(cached) = navigation_cache_lock.get(id) {
-            return Ok((*cached).clone());
-        };
-
-        fn osstr_to_str(osstr: &OsStr) -> Result<&str, GetComicNavigationError> {
-            osstr.to_str().map_or(
-                Err(GetComicNavigationError::CantConvertOsToString(
-                    osstr.to_os_string(),
-                )),
-                |x| Ok(x),
-            )
+            return Ok(cached.clone());
         };
 
-        let mut result = Vec::new();
-        let comic_directory = &self
+        let entry = self
             .comics
             .get(id)
-            .map_or(Err(GetComicNavigationError::ComicDontExist(id)), |x| Ok(x))?
-            .0;
-
-        let paths = read_dir(comic_directory).map_err(|err| {
-            GetComicNavigationError::CantReadDirectory(err, comic_directory.clone())
-        })?;
-        for path in paths {
-            let path = path
-                .map_err(|err| {
-                    GetComicNavigationError::CantReadDirEntry(err, comic_directory.clone())
-                })?
-                .path();
-            let file_name = osstr_to_str(path.file_name().map_or(
-                Err(GetComicNavigationError::FileWithNoName(path.clone())),
-                |x| Ok(x),
-            )?)?;
-
-            if file_name == "data.json" {
-                continue;
+            .ok_or(GetComicNavigationError::ComicDontExist(id))?;
+        #[cfg(feature = "sqlite")]
+        {
+            if let Some(navigation) = self.read_catalog_navigation(id, entry) {
+                return navigation.map(Arc::new);
             };
+        }
+        let result = Arc::new(self.read_navigation(
+            &*entry.pages,
+            &entry.comic,
+            entry.root,
+            entry.modified,
+        )?);
 
-            if let Some(Some("tmp")) = path.extension().map(|x| x.to_str()) {
-                continue;
-            };
+        navigation_cache_lock.insert(id, result.clone());
+        Ok(result)
+    }
 
-            let file_stem = osstr_to_str(path.file_stem().map_or(
-                Err(GetComicNavigationError::FileWithNoStem(path.clone())),
-                |x| Ok(x),
-            )?)?;
-
-            let (part, page) = {
-                let mut splited = file_stem.split("-");
-                let first_part_string = splited.next().map_or(
-                    Err(GetComicNavigationError::CantGetSplitedDash(
-                        0,
-                        file_stem.to_string(),
-                    )),
-                    |x| Ok(x),
-                )?;
-                let first_part = first_part_string.parse::<usize>().map_err(|err| {
-                    GetComicNavigationError::CantConvertStringFromPathToInt(
-                        err,
-                        first_part_string.to_string(),
-                        path.clone(),
-                    )
-                })?;
-
-                let second_part_string = splited.next().map_or(
-                    Err(GetComicNavigationError::CantGetSplitedDash(
-                        0,
-                        file_stem.to_string(),
-                    )),
-                    |x| Ok(x),
-                )?;
-
-                let second_part = second_part_string.parse::<usize>().map_err(|err| {
-                    GetComicNavigationError::CantConvertStringFromPathToInt(
-                        err,
-                        first_part_string.to_string(),
-                        path.clone(),
-                    )
-                })?;
+    /// Compute the chapters and pages of a comic from the pages in the catalog, if there is one
+    /// and they are still valid
+    #[cfg(feature = "sqlite")]
+    fn read_catalog_navigation(
+        &self,
+        id: usize,
+        entry: &ComicEntry,
+    ) -> Option<Result<ComicNavigation, GetComicNavigationError>> {
+        let catalog = self.catalog.as_ref()?;
+        let naming = self.page_naming_of(&entry.comic, entry.root);
+        let state = CatalogState::of(entry, naming).ok()?;
+        let files = catalog.pages(id, &state).ok()??;
+        Some(ComicNavigation::from_files(
+            &*entry.pages,
+            files,
+            &entry.comic,
+            naming,
+        ))
+    }
+
+    /// Compute the chapters and pages of a comic found in the library root with the index
+    /// `root`, that may not be in this database yet
+    fn read_navigation(
+        &self,
+        pages: &dyn PageSource,
+        comic: &Comic,
+        root: usize,
+        modified: Option<SystemTime>,
+    ) -> Result<ComicNavigation, GetComicNavigationError> {
+        let files = list_source_files(self.listing_cache.as_deref(), pages, modified)?;
+        ComicNavigation::from_files(pages, files, comic, self.page_naming_of(comic, root))
+    }
+
+    /// The source of the pages of a comic
+    pub fn get_page_source(&self, id: usize) -> Option<&Arc<dyn PageSource>> {
+        self.comics.get(id).map(|entry| &entry.pages)
+    }
+
+    /// Open a document of a page for reading. `document` is its position in
+    /// [`Page::documents`](crate::Page::documents).
+    pub fn open_page(
+        &self,
+        id: usize,
+        page_id: PageId,
+        document: usize,
+    ) -> Result<PageReader, OpenPageError> {
+        let navigation = self.get_comic_navigation(id)?;
+        let document = navigation
+            .page(page_id)
+            .ok_or(OpenPageError::PageDontExist(page_id))?
+            .document(document)
+            .ok_or(OpenPageError::DocumentDontExist(page_id, document))?;
+        // the comic exist, as its navigation was found
+        let entry = self.comics.get(id).unwrap();
+        Ok(entry.pages.open(document.name())?)
+    }
+
+    /// The keyword index. It is empty if the keywords are read from a catalog.
+    pub fn keywords(&self) -> &HashMap<String, HashMap<String, Vec<usize>>> {
+        &self.keywords
+    }
+
+    /// The keyword index, built from the comics if it isn't kept in memory
+    fn keyword_index(&self) -> Cow<HashMap<String, HashMap<String, Vec<usize>>>> {
+        if self.keeps_keyword_index() {
+            return Cow::Borrowed(&self.keywords);
+        };
+        let mut keywords = HashMap::new();
+        for entry in self.comics.values() {
+            index_keywords(&mut keywords, &entry.comic);
+        }
+        Cow::Owned(keywords)
+    }
+
+    /// The keyword categories, with their keywords, both sorted by name. They are read from
+    /// the catalog if there is one and it is filled, and from the comics otherwise.
+    pub fn keyword_list(&self) -> Vec<(String, Vec<String>)> {
+        #[cfg(feature = "sqlite")]
+        {
+            if let Some(Ok(list)) = self.synced_catalog().map(Catalog::keyword_list) {
+                return list;
+            };
+        }
+        let mut list: Vec<(String, Vec<String>)> = self
+            .keyword_index()
+            .iter()
+            .map(|(category, keywords)| {
+                let mut keywords: Vec<String> = keywords.keys().cloned().collect();
+                keywords.sort();
+                (category.clone(), keywords)
+            })
+            .collect();
+        list.sort();
+        list
+    }
 
-                //TODO: handle the case with multiple document by page
-                (first_part, second_part)
+    /// The ids of the comics with this keyword, in id order. They are read from the catalog if
+    /// there is one and it is filled, and from the comics otherwise.
+    pub fn comics_with_keyword(&self, category: &str, keyword: &str) -> Vec<usize> {
+        #[cfg(feature = "sqlite")]
+        {
+            let catalog_ids = self
+                .synced_catalog()
+                .map(|catalog| catalog.comics_with_keyword(category, keyword));
+            if let Some(Ok(comic_ids)) = catalog_ids {
+                return comic_ids;
             };
+        }
+        let mut comic_ids = self
+            .keyword_index()
+            .get(category)
+            .and_then(|keywords| keywords.get(keyword))
+            .cloned()
+            .unwrap_or_default();
+        comic_ids.sort_unstable();
+        comic_ids.dedup();
+        comic_ids
+    }
+}
 
-            while result.len() <= part as usize {
-                result.push(Vec::new())
+/// Add the keywords of `comic`, and the languages it is the translation of, to a keyword index
+fn index_keywords(keywords: &mut HashMap<String, HashMap<String, Vec<usize>>>, comic: &Comic) {
+    for (keyword_category, values) in &comic.keywords {
+        if let Some(keyword_hashmap) = keywords.get_mut(keyword_category) {
+            for section_name in values {
+                if let Some(section_vec) = keyword_hashmap.get_mut(section_name) {
+                    section_vec.push(comic.id);
+                } else {
+                    keyword_hashmap.insert(section_name.to_string(), vec![comic.id]);
+                }
             }
-
-            while result[part as usize].len() <= page as usize {
-                result[part as usize].push(None)
+        } else {
+            let mut new_category_map: HashMap<String, Vec<usize>> = HashMap::new();
+            for section_name in values {
+                if let Some(section_vec) = new_category_map.get_mut(section_name) {
+                    section_vec.push(comic.id);
+                } else {
+                    new_category_map.insert(section_name.to_string(), vec![comic.id]);
+                }
             }
+            keywords.insert(keyword_category.clone(), new_category_map);
+        }
+    };
 
-            result[part as usize][page as usize] = Some(path.into());
+    if !keywords.contains_key("translation") {
+        keywords.insert("translation".into(), HashMap::new());
+    };
+    let trans_hashmap = keywords.get_mut("translation").unwrap(); //TODO: check for a get_key_or_create
+    for (trans_lang, trans_comic_ref) in &comic.translations {
+        if comic.is_referenced_by(trans_comic_ref) {
+            if let Some(lang_vec) = trans_hashmap.get_mut(trans_lang) {
+                lang_vec.push(comic.id);
+            } else {
+                trans_hashmap.insert(trans_lang.to_string(), vec![comic.id]);
+            }
         }
+    };
+}
 
-        navigation_cache_lock.insert(id, result.clone());
-        Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectorySource;
+
+    fn comic(declared_id: Option<usize>, slug: Option<&str>) -> Comic {
+        let mut comic: Comic =
+            serde_json::from_str(r#"{"keywords": {}, "translations": []}"#).unwrap();
+        comic.declared_id = declared_id;
+        comic.slug = slug.map(str::to_string);
+        comic
     }
 
-    pub fn keywords(&self) -> &HashMap<String, HashMap<String, Vec<usize>>> {
-        &self.keywords
+    fn add(
+        database: &mut ComicDatabase,
+        name: &str,
+        comic: Comic,
+    ) -> Result<Option<usize>, ComicDatabaseLoadError> {
+        let path = PathBuf::from("/nonexistent/library").join(name);
+        let pages = Arc::new(DirectorySource::new(path.clone()));
+        database.add_comic(path, pages, 0, comic)
+    }
+
+    #[test]
+    fn slugs_get_unused_ids() {
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        assert_eq!(
+            add(&mut database, "a", comic(Some(3), None)).unwrap(),
+            Some(3)
+        );
+        let first = add(&mut database, "b", comic(None, Some("first")))
+            .unwrap()
+            .unwrap();
+        let second = add(&mut database, "c", comic(None, Some("second")))
+            .unwrap()
+            .unwrap();
+        assert_eq!((first, second), (4, 5));
+        assert_eq!(database.resolve(&ComicRef::from("first")), Some(first));
+        assert_eq!(database.resolve(&ComicRef::from("second")), Some(second));
+        assert_eq!(database.resolve(&ComicRef::from("3")), Some(3));
+    }
+
+    #[test]
+    fn declared_id_moves_the_comic_using_it() {
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        assert_eq!(
+            add(&mut database, "a", comic(None, Some("moved"))).unwrap(),
+            Some(0)
+        );
+        assert_eq!(
+            add(&mut database, "b", comic(Some(0), None)).unwrap(),
+            Some(0)
+        );
+
+        let moved = database.resolve(&ComicRef::from("moved")).unwrap();
+        assert_ne!(moved, 0);
+        assert_eq!(database.get_comic(moved).unwrap().id, moved);
+        assert_eq!(database.get_comic(0).unwrap().declared_id, Some(0));
+        assert_eq!(database.comics().len(), 2);
+    }
+
+    #[test]
+    fn duplicate_slugs_follow_the_policy() {
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        add(&mut database, "a", comic(None, Some("same"))).unwrap();
+        assert!(matches!(
+            add(&mut database, "b", comic(None, Some("same"))),
+            Err(ComicDatabaseLoadError::IdCollision { .. })
+        ));
+
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+        let first = add(&mut database, "a", comic(None, Some("same")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            add(&mut database, "b", comic(None, Some("same"))).unwrap(),
+            None
+        );
+        assert_eq!(database.resolve(&ComicRef::from("same")), Some(first));
+        assert_eq!(database.duplicate_ids().len(), 1);
+
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Skip);
+        add(&mut database, "a", comic(Some(1), Some("same"))).unwrap();
+        assert_eq!(
+            add(&mut database, "b", comic(None, Some("same"))).unwrap(),
+            None
+        );
+        assert!(database.resolve(&ComicRef::from("same")).is_none());
+        assert!(database.get_comic(1).is_none());
+    }
+
+    #[test]
+    fn failed_rescan_keeps_the_comic() {
+        let library =
+            std::env::temp_dir().join(format!("marblecomic-rescan-{}", std::process::id()));
+        let folder = library.join("comic");
+        let _ = std::fs::remove_dir_all(&library);
+        std::fs::create_dir_all(&folder).unwrap();
+        let data = r#"{"id": 4, "slug": "kept", "keywords": {}, "translations": []}"#;
+        std::fs::write(folder.join("data.json"), data).unwrap();
+        std::fs::write(folder.join("00000-00000.png"), "page").unwrap();
+
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        database.load_from_dir(library.clone()).unwrap();
+        assert!(database.get_comic_navigation(4).is_ok());
+
+        // a page that doesn't follow the naming
+        std::fs::write(folder.join("cover.png"), "page").unwrap();
+        assert!(matches!(
+            database.rescan_comic(4),
+            Err(RescanComicError::Navigation(_))
+        ));
+        assert_eq!(database.resolve(&ComicRef::from("kept")), Some(4));
+        std::fs::remove_file(folder.join("cover.png")).unwrap();
+
+        // an invalid slug
+        let data = r#"{"id": 4, "slug": "not kept", "keywords": {}, "translations": []}"#;
+        std::fs::write(folder.join("data.json"), data).unwrap();
+        assert!(matches!(
+            database.rescan_comic(4),
+            Err(RescanComicError::Load(ComicDatabaseLoadError::InvalidSlug(
+                ..
+            )))
+        ));
+        assert_eq!(database.resolve(&ComicRef::from("kept")), Some(4));
+        assert_eq!(database.get_comic_navigation(4).unwrap().page_count(), 1);
+
+        std::fs::remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn invalid_identifiers_are_rejected() {
+        let mut database = ComicDatabase::new(DuplicateIdPolicy::Fail);
+        assert!(matches!(
+            add(&mut database, "a", comic(None, None)),
+            Err(ComicDatabaseLoadError::NoIdentifier(_))
+        ));
+        for slug in &["", "123", "with space", "a/b"] {
+            assert!(matches!(
+                add(&mut database, "a", comic(None, Some(slug))),
+                Err(ComicDatabaseLoadError::InvalidSlug(..))
+            ));
+        }
+        assert!(is_valid_slug("a-comic_v2.0"));
     }
 }
@@ -0,0 +1,428 @@
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use thiserror::Error;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{rename, File};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{ChapterInfo, Comic, ComicNavigation, PageNaming};
+
+/// The name of the ComicInfo.xml file, in a comic folder or at the root of an archive
+pub const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+#[derive(Error, Debug)]
+pub enum ComicInfoError {
+    #[error("can't parse the ComicInfo.xml")]
+    CantParse(#[source] quick_xml::Error),
+    #[error("can't write the ComicInfo.xml")]
+    CantWrite(#[source] quick_xml::Error),
+    #[error("can't create the file {1}")]
+    CantCreateFile(#[source] io::Error, PathBuf),
+    #[error("can't write the file {1}")]
+    CantWriteFile(#[source] io::Error, PathBuf),
+}
+
+/// The fields of a ComicInfo.xml (the metadata format of ComicRack) that are used by
+/// marblecomic. The other ones are ignored.
+///
+/// Genre, Tags and Writer are comma separated lists, stored in the `genre`, `tags` and
+/// `writer` keyword categories, and LanguageISO is stored in the `language` one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComicInfo {
+    pub title: Option<String>,
+    pub series: Option<String>,
+    pub summary: Option<String>,
+    pub writer: Option<String>,
+    pub genre: Option<String>,
+    pub page_count: Option<usize>,
+    pub language_iso: Option<String>,
+    pub tags: Option<String>,
+}
+
+const GENRE_CATEGORY: &str = "genre";
+const TAGS_CATEGORY: &str = "tags";
+const WRITER_CATEGORY: &str = "writer";
+const LANGUAGE_CATEGORY: &str = "language";
+
+impl ComicInfo {
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, ComicInfoError> {
+        let mut reader = Reader::from_reader(reader);
+        reader.trim_text(true);
+        let mut info = Self::default();
+        let mut buffer = Vec::new();
+        // the names of the elements containing the current one
+        let mut parents: Vec<Vec<u8>> = Vec::new();
+        loop {
+            let value = match reader
+                .read_event(&mut buffer)
+                .map_err(ComicInfoError::CantParse)?
+            {
+                Event::Start(element) => {
+                    parents.push(element.name().to_vec());
+                    None
+                }
+                Event::End(_) => {
+                    parents.pop();
+                    None
+                }
+                Event::Text(text) => Some(
+                    text.unescape_and_decode(&reader)
+                        .map_err(ComicInfoError::CantParse)?,
+                ),
+                // the content of a CDATA section isn't escaped
+                Event::CData(text) => Some(
+                    reader
+                        .decode(&text)
+                        .map_err(ComicInfoError::CantParse)?
+                        .to_string(),
+                ),
+                Event::Eof => break,
+                _ => None,
+            };
+            // the fields are the children of the root ComicInfo element
+            if let (Some(value), [_, field]) = (value, parents.as_slice()) {
+                info.set_field(field, value);
+            };
+            buffer.clear();
+        }
+        Ok(info)
+    }
+
+    fn set_field(&mut self, name: &[u8], value: String) {
+        match name {
+            b"Title" => self.title = Some(value),
+            b"Series" => self.series = Some(value),
+            b"Summary" => self.summary = Some(value),
+            b"Writer" => self.writer = Some(value),
+            b"Genre" => self.genre = Some(value),
+            b"PageCount" => self.page_count = value.parse().ok(),
+            b"LanguageISO" => self.language_iso = Some(value),
+            b"Tags" => self.tags = Some(value),
+            _ => (),
+        }
+    }
+
+    /// The fields that are set, in the order of the ComicInfo.xml schema
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let fields = vec![
+            ("Title", self.title.clone()),
+            ("Series", self.series.clone()),
+            ("Summary", self.summary.clone()),
+            ("Writer", self.writer.clone()),
+            ("Genre", self.genre.clone()),
+            ("PageCount", self.page_count.map(|count| count.to_string())),
+            ("LanguageISO", self.language_iso.clone()),
+            ("Tags", self.tags.clone()),
+        ];
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect()
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), ComicInfoError> {
+        let mut writer = Writer::new_with_indent(writer, b' ', 2);
+        let root = BytesStart::borrowed_name(b"ComicInfo").with_attributes(vec![
+            ("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"),
+            ("xmlns:xsd", "http://www.w3.org/2001/XMLSchema"),
+        ]);
+        let mut events = vec![
+            Event::Decl(BytesDecl::new(b"1.0", Some(b"utf-8"), None)),
+            Event::Start(root),
+        ];
+        let fields = self.fields();
+        for (name, value) in &fields {
+            events.push(Event::Start(BytesStart::borrowed_name(name.as_bytes())));
+            events.push(Event::Text(BytesText::from_plain_str(value)));
+            events.push(Event::End(BytesEnd::borrowed(name.as_bytes())));
+        }
+        events.push(Event::End(BytesEnd::borrowed(b"ComicInfo")));
+        for event in events {
+            writer
+                .write_event(event)
+                .map_err(ComicInfoError::CantWrite)?;
+        }
+        writer
+            .inner()
+            .write_all(b"\n")
+            .map_err(|err| ComicInfoError::CantWrite(err.into()))
+    }
+
+    /// Write this ComicInfo.xml at `path`, replacing the existing file. It is written to a
+    /// temporary file first, so the existing file is left untouched if it fails.
+    pub fn save(&self, path: &Path) -> Result<(), ComicInfoError> {
+        let mut temporary_path = path.as_os_str().to_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let temporary_file = File::create(&temporary_path)
+            .map_err(|err| ComicInfoError::CantCreateFile(err, temporary_path.clone()))?;
+        let mut writer = io::BufWriter::new(temporary_file);
+        self.write(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(io::Error::from)
+            .and_then(|temporary_file| temporary_file.sync_all())
+            .map_err(|err| ComicInfoError::CantWriteFile(err, temporary_path.clone()))?;
+        rename(&temporary_path, path)
+            .map_err(|err| ComicInfoError::CantWriteFile(err, path.to_path_buf()))
+    }
+
+    /// The comic described by this ComicInfo.xml. `name` is the name of its folder or archive,
+    /// used to make its slug.
+    ///
+    /// A ComicInfo.xml describe a single book, so its pages are read with the single chapter
+    /// naming, and the Title is the title of this chapter (or the comic name if there is no
+    /// Series).
+    pub fn to_comic(&self, name: &str) -> Comic {
+        let mut keywords: HashMap<String, Vec<String>> = HashMap::new();
+        let lists = [
+            (GENRE_CATEGORY, &self.genre),
+            (TAGS_CATEGORY, &self.tags),
+            (WRITER_CATEGORY, &self.writer),
+        ];
+        for (category, list) in lists.iter() {
+            let values: Vec<String> = list
+                .iter()
+                .flat_map(|list| list.split(','))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect();
+            if !values.is_empty() {
+                keywords.insert(category.to_string(), values);
+            };
+        }
+        if let Some(language) = &self.language_iso {
+            keywords.insert(LANGUAGE_CATEGORY.to_string(), vec![language.clone()]);
+        };
+
+        let (comic_name, chapter_title) = match (&self.series, &self.title) {
+            (Some(series), title) => (Some(series.clone()), title.clone()),
+            (None, title) => (title.clone(), None),
+        };
+        let mut chapters = BTreeMap::new();
+        if chapter_title.is_some() {
+            chapters.insert(
+                0,
+                ChapterInfo {
+                    title: chapter_title,
+                    ..ChapterInfo::default()
+                },
+            );
+        };
+
+        Comic {
+            id: 0,
+            declared_id: None,
+            slug: slug_from_name(name),
+            comic_name,
+            description: self.summary.clone(),
+            keywords,
+            translations: Vec::new(),
+            found: true,
+            chapters,
+            chapter_groups: Vec::new(),
+            page_naming: Some(PageNaming::SingleChapter),
+            page_count: self.page_count,
+        }
+    }
+
+    /// The ComicInfo.xml describing this comic. The title is only set if the comic has a single
+    /// chapter.
+    pub fn from_comic(comic: &Comic, navigation: &ComicNavigation) -> Self {
+        let join_keywords = |category: &str| {
+            comic
+                .keywords
+                .get(category)
+                .filter(|values| !values.is_empty())
+                .map(|values| values.join(", "))
+        };
+        let title = match navigation.chapters().collect::<Vec<_>>().as_slice() {
+            [(chapter_id, _)] => navigation.chapter_title(*chapter_id).map(String::from),
+            _ => None,
+        };
+        Self {
+            title,
+            series: comic.comic_name.clone(),
+            summary: comic.description.clone(),
+            writer: join_keywords(WRITER_CATEGORY),
+            genre: join_keywords(GENRE_CATEGORY),
+            page_count: Some(navigation.page_count()),
+            language_iso: comic
+                .keywords
+                .get(LANGUAGE_CATEGORY)
+                .and_then(|languages| languages.first())
+                .cloned(),
+            tags: join_keywords(TAGS_CATEGORY),
+        }
+    }
+}
+
+/// A slug made from a folder or file name, like `my-comic-vol.1` for `My Comic Vol.1`.
+/// Return `None` if there is no letter or digit in the name.
+fn slug_from_name(name: &str) -> Option<String> {
+    let mut slug = String::new();
+    for char in name.chars() {
+        if char.is_ascii_alphanumeric() || char == '_' || char == '.' {
+            slug.push(char.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        };
+    }
+    let slug = slug.trim_end_matches('-');
+    if !slug.chars().any(|char| char.is_ascii_alphanumeric()) {
+        None
+    } else if slug.chars().all(|char| char.is_ascii_digit()) {
+        // a slug that is only digits would be confused with an id
+        Some(format!("comic-{}", slug))
+    } else {
+        Some(slug.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+
+    const COMIC_RACK_SAMPLE: &str = r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:xsd="http://www.w3.org/2001/XMLSchema">
+  <Title>The &quot;First&quot; Steps</Title>
+  <Series>Tom &amp; Jerry</Series>
+  <Number>1</Number>
+  <Summary><![CDATA[A <b>cat</b> & a mouse]]></Summary>
+  <Writer>Alice Doe, Bob Roe</Writer>
+  <Genre>Comedy,  Animals ,</Genre>
+  <Web>https://example.com/?a=1&amp;b=2</Web>
+  <PageCount>24</PageCount>
+  <LanguageISO>en</LanguageISO>
+  <Pages>
+    <Page Image="0" Type="FrontCover" />
+    <Page Image="1"><Title>not the title of the comic</Title></Page>
+  </Pages>
+  <Tags></Tags>
+</ComicInfo>
+"#;
+
+    fn category<'a>(comic: &'a Comic, category: &str) -> Option<&'a [String]> {
+        comic.keywords.get(category).map(Vec::as_slice)
+    }
+
+    #[test]
+    fn comic_rack_files_are_read() {
+        let info = ComicInfo::read(COMIC_RACK_SAMPLE.as_bytes()).unwrap();
+        assert_eq!(
+            info,
+            ComicInfo {
+                title: Some("The \"First\" Steps".to_string()),
+                series: Some("Tom & Jerry".to_string()),
+                summary: Some("A <b>cat</b> & a mouse".to_string()),
+                writer: Some("Alice Doe, Bob Roe".to_string()),
+                genre: Some("Comedy,  Animals ,".to_string()),
+                page_count: Some(24),
+                language_iso: Some("en".to_string()),
+                tags: None,
+            }
+        );
+    }
+
+    #[test]
+    fn lists_are_split_into_keywords() {
+        let comic = ComicInfo::read(COMIC_RACK_SAMPLE.as_bytes())
+            .unwrap()
+            .to_comic("Tom & Jerry 01");
+        assert_eq!(
+            category(&comic, "writer"),
+            Some(&["Alice Doe".to_string(), "Bob Roe".to_string()][..])
+        );
+        assert_eq!(
+            category(&comic, "genre"),
+            Some(&["Comedy".to_string(), "Animals".to_string()][..])
+        );
+        assert_eq!(category(&comic, "language"), Some(&["en".to_string()][..]));
+        assert_eq!(category(&comic, "tags"), None);
+        assert_eq!(comic.page_count, Some(24));
+        assert_eq!(comic.page_naming, Some(PageNaming::SingleChapter));
+    }
+
+    #[test]
+    fn series_is_the_comic_name() {
+        let info = ComicInfo {
+            title: Some("Chapter title".to_string()),
+            series: Some("Series".to_string()),
+            ..ComicInfo::default()
+        };
+        let comic = info.to_comic("series");
+        assert_eq!(comic.comic_name, Some("Series".to_string()));
+        assert_eq!(
+            comic.chapters.get(&0).and_then(|chapter| chapter.title.clone()),
+            Some("Chapter title".to_string())
+        );
+
+        // without a series, the title is the comic name
+        let info = ComicInfo {
+            title: Some("Title".to_string()),
+            ..ComicInfo::default()
+        };
+        let comic = info.to_comic("title");
+        assert_eq!(comic.comic_name, Some("Title".to_string()));
+        assert!(comic.chapters.is_empty());
+
+        let comic = ComicInfo::default().to_comic("untitled");
+        assert_eq!(comic.comic_name, None);
+        assert!(comic.chapters.is_empty());
+    }
+
+    #[test]
+    fn written_files_are_read_back() {
+        let info = ComicInfo {
+            title: Some("<Title> & \"quotes\"".to_string()),
+            series: Some("Série".to_string()),
+            summary: Some("first line\nsecond line".to_string()),
+            writer: Some("Someone".to_string()),
+            genre: Some("sf, comedy".to_string()),
+            page_count: Some(3),
+            language_iso: Some("fr".to_string()),
+            tags: Some("tag".to_string()),
+        };
+        let mut written = Vec::new();
+        info.write(&mut written).unwrap();
+        assert_eq!(ComicInfo::read(written.as_slice()).unwrap(), info);
+
+        let folder = std::env::temp_dir().join(format!(
+            "marblecomic-comic-info-{}",
+            std::process::id()
+        ));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        let path = folder.join(COMIC_INFO_FILE);
+        std::fs::write(&path, "previous content").unwrap();
+        info.save(&path).unwrap();
+        assert_eq!(read_to_string(&path).unwrap().as_bytes(), written.as_slice());
+        assert!(!folder.join("ComicInfo.xml.tmp").exists());
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn slugs_are_made_from_names() {
+        assert_eq!(
+            slug_from_name("My Comic Vol.1"),
+            Some("my-comic-vol.1".to_string())
+        );
+        assert_eq!(
+            slug_from_name("  Spaces & symbols!! "),
+            Some("spaces-symbols".to_string())
+        );
+        assert_eq!(slug_from_name("under_score"), Some("under_score".to_string()));
+        // only digits would be taken for an id
+        assert_eq!(slug_from_name("2019"), Some("comic-2019".to_string()));
+        assert_eq!(slug_from_name("12 - 34"), Some("12-34".to_string()));
+        // nothing to make a slug from
+        assert_eq!(slug_from_name("!!!"), None);
+        assert_eq!(slug_from_name("..."), None);
+        assert_eq!(slug_from_name(""), None);
+        assert_eq!(slug_from_name("日本語"), None);
+    }
+}
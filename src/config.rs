@@ -0,0 +1,355 @@
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{DuplicateIdPolicy, PageNaming};
+
+/// The default name of the configuration file, looked up in the current directory
+pub const DEFAULT_CONFIG_FILE: &str = "marblecomic.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("can't open the configuration file at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't read the configuration file at {1}")]
+    CantReadFile(#[source] io::Error, PathBuf),
+    #[error("can't parse the configuration file at {1}")]
+    CantParseFile(#[source] toml::de::Error, PathBuf),
+    #[error("no library root is configured (use library_roots in the config file or --library)")]
+    NoLibraryRoot,
+    #[error("the library root {0} doesn't exist or isn't a directory")]
+    LibraryRootNotADirectory(PathBuf),
+    #[error("the static directory {0} doesn't exist or isn't a directory")]
+    StaticDirNotADirectory(PathBuf),
+    #[error("the directory containing the tracker file {0} doesn't exist")]
+    TrackerDirDontExist(PathBuf),
+    #[error("the directory containing the navigation cache {0} doesn't exist")]
+    NavigationCacheDirDontExist(PathBuf),
+    #[error("the directory containing the catalog {0} doesn't exist")]
+    CatalogDirDontExist(PathBuf),
+    #[error("a catalog is configured, but marblecomic was built without the sqlite feature")]
+    CatalogNotSupported,
+    #[error("no user file is configured (use users_file in the config file or --users)")]
+    NoUserFile,
+    #[error("the directory containing the user file {0} doesn't exist")]
+    UserFileDirDontExist(PathBuf),
+    #[error("a page naming is set for {0}, which isn't a library root")]
+    PageNamingForUnknownRoot(PathBuf),
+}
+
+/// A partial configuration, as read from a config file or from the command line.
+///
+/// Every field is optional: unset values are taken from a lower precedence source
+/// (see [`ConfigFile::merge`]), and finally from the default of [`MarbleOptions`].
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub library_roots: Option<Vec<PathBuf>>,
+    pub tracker_path: Option<PathBuf>,
+    pub tracker_backups: Option<usize>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub enable_progress_writing: Option<bool>,
+    pub static_dir: Option<PathBuf>,
+    pub duplicate_id_policy: Option<DuplicateIdPolicy>,
+    pub lenient_loading: Option<bool>,
+    /// In seconds, 0 to disable
+    pub rescan_interval: Option<u64>,
+    pub admin_token: Option<String>,
+    /// The page naming of some library roots, by path
+    pub page_naming: Option<HashMap<PathBuf, PageNaming>>,
+    pub navigation_cache: Option<PathBuf>,
+    pub scan_threads: Option<usize>,
+    pub catalog: Option<PathBuf>,
+    pub users_file: Option<PathBuf>,
+    pub secret_key: Option<String>,
+    pub admin_users: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    /// Read a toml configuration file. Relative paths inside it are resolved
+    /// relative to the folder containing the file.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let mut file =
+            File::open(path).map_err(|err| ConfigError::CantOpenFile(err, path.to_path_buf()))?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|err| ConfigError::CantReadFile(err, path.to_path_buf()))?;
+        let mut config: ConfigFile = toml::from_str(&content)
+            .map_err(|err| ConfigError::CantParseFile(err, path.to_path_buf()))?;
+
+        if let Some(base) = path.parent() {
+            config.resolve_relative_to(base);
+        };
+        Ok(config)
+    }
+
+    fn resolve_relative_to(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = base.join(&path);
+            }
+        };
+        if let Some(roots) = &mut self.library_roots {
+            roots.iter_mut().for_each(resolve);
+        };
+        if let Some(tracker_path) = &mut self.tracker_path {
+            resolve(tracker_path);
+        };
+        if let Some(static_dir) = &mut self.static_dir {
+            resolve(static_dir);
+        };
+        if let Some(navigation_cache) = &mut self.navigation_cache {
+            resolve(navigation_cache);
+        };
+        if let Some(catalog) = &mut self.catalog {
+            resolve(catalog);
+        };
+        if let Some(users_file) = &mut self.users_file {
+            resolve(users_file);
+        };
+        if let Some(page_naming) = self.page_naming.take() {
+            self.page_naming = Some(
+                page_naming
+                    .into_iter()
+                    .map(|(mut root, naming)| {
+                        resolve(&mut root);
+                        (root, naming)
+                    })
+                    .collect(),
+            );
+        };
+    }
+
+    /// Merge two configurations. Values set in `other` take precedence over the ones of `self`.
+    pub fn merge(self, other: ConfigFile) -> ConfigFile {
+        ConfigFile {
+            library_roots: other.library_roots.or(self.library_roots),
+            tracker_path: other.tracker_path.or(self.tracker_path),
+            tracker_backups: other.tracker_backups.or(self.tracker_backups),
+            address: other.address.or(self.address),
+            port: other.port.or(self.port),
+            enable_progress_writing: other
+                .enable_progress_writing
+                .or(self.enable_progress_writing),
+            static_dir: other.static_dir.or(self.static_dir),
+            duplicate_id_policy: other.duplicate_id_policy.or(self.duplicate_id_policy),
+            lenient_loading: other.lenient_loading.or(self.lenient_loading),
+            rescan_interval: other.rescan_interval.or(self.rescan_interval),
+            admin_token: other.admin_token.or(self.admin_token),
+            page_naming: other.page_naming.or(self.page_naming),
+            navigation_cache: other.navigation_cache.or(self.navigation_cache),
+            scan_threads: other.scan_threads.or(self.scan_threads),
+            catalog: other.catalog.or(self.catalog),
+            users_file: other.users_file.or(self.users_file),
+            secret_key: other.secret_key.or(self.secret_key),
+            admin_users: other.admin_users.or(self.admin_users),
+        }
+    }
+}
+
+/// The validated configuration of the server
+#[derive(Debug, Clone)]
+pub struct MarbleOptions {
+    pub library_roots: Vec<PathBuf>,
+    pub tracker_path: PathBuf,
+    /// The number of previous versions of the progress files that are kept
+    pub tracker_backups: usize,
+    /// The address to bind to. If `None`, the default of the rocket environment is used.
+    pub address: Option<String>,
+    /// The port to bind to. If `None`, the default of the rocket environment is used.
+    pub port: Option<u16>,
+    pub enable_progress_writing: bool,
+    pub static_dir: PathBuf,
+    pub duplicate_id_policy: DuplicateIdPolicy,
+    /// If true, comics that can't be loaded are skipped instead of preventing the server to start
+    pub lenient_loading: bool,
+    /// How often the library is reloaded. If `None`, it is only loaded at startup.
+    pub rescan_interval: Option<Duration>,
+    /// The token required by the admin actions. If `None`, they can only be used by the
+    /// `admin_users`.
+    pub admin_token: Option<String>,
+    /// The page naming of the library roots that don't use the standard one
+    pub page_naming: HashMap<PathBuf, PageNaming>,
+    /// Where the files of the comics are cached between restarts. If `None`, they are listed
+    /// again at each start.
+    pub navigation_cache: Option<PathBuf>,
+    /// The number of threads reading the comics when the library is loaded at startup
+    pub scan_threads: usize,
+    /// Where the SQLite catalog of the library is stored. It can only be set if marblecomic is
+    /// built with the `sqlite` feature.
+    pub catalog: Option<PathBuf>,
+    /// Where the user accounts are stored. If `None`, the accounts are disabled, and everybody
+    /// share the same progress.
+    pub users_file: Option<PathBuf>,
+    /// The key the session cookies are encrypted with. If `None`, a new one is generated at each
+    /// start, so the users have to log in again.
+    pub secret_key: Option<String>,
+    /// The users that can use the admin actions once logged in
+    pub admin_users: Vec<String>,
+}
+
+impl MarbleOptions {
+    /// Fill the missing values with their default, and check that the configured paths are usable
+    pub fn from_config(config: ConfigFile) -> Result<Self, ConfigError> {
+        let library_roots = config.library_roots.unwrap_or_default();
+        if library_roots.is_empty() {
+            return Err(ConfigError::NoLibraryRoot);
+        };
+        for root in &library_roots {
+            if !root.is_dir() {
+                return Err(ConfigError::LibraryRootNotADirectory(root.clone()));
+            };
+        }
+
+        // the roots may be written differently in the configuration file and in the command
+        // line, so they are compared once canonicalized, and the page naming is kept under the
+        // path of the library root
+        let canonical_roots: Vec<Option<PathBuf>> = library_roots
+            .iter()
+            .map(|root| root.canonicalize().ok())
+            .collect();
+        let mut page_naming = HashMap::new();
+        for (root, naming) in config.page_naming.unwrap_or_default() {
+            let canonical = root.canonicalize().ok();
+            let library_root = library_roots
+                .iter()
+                .zip(&canonical_roots)
+                .find(|(library_root, canonical_root)| {
+                    **library_root == root || (canonical.is_some() && **canonical_root == canonical)
+                })
+                .map(|(library_root, _)| library_root.clone())
+                .ok_or(ConfigError::PageNamingForUnknownRoot(root))?;
+            page_naming.insert(library_root, naming);
+        }
+
+        // only the server needs it, so it is checked by `check_static_dir`
+        let static_dir = config.static_dir.unwrap_or_else(|| PathBuf::from("static"));
+
+        let tracker_path = config
+            .tracker_path
+            .unwrap_or_else(|| PathBuf::from("./progress.json"));
+        if let Some(parent) = tracker_path.parent() {
+            if parent != Path::new("") && !parent.is_dir() {
+                return Err(ConfigError::TrackerDirDontExist(tracker_path));
+            };
+        };
+
+        if let Some(navigation_cache) = &config.navigation_cache {
+            if let Some(parent) = navigation_cache.parent() {
+                if parent != Path::new("") && !parent.is_dir() {
+                    return Err(ConfigError::NavigationCacheDirDontExist(
+                        navigation_cache.clone(),
+                    ));
+                };
+            };
+        };
+
+        if let Some(catalog) = &config.catalog {
+            if !cfg!(feature = "sqlite") {
+                return Err(ConfigError::CatalogNotSupported);
+            };
+            if let Some(parent) = catalog.parent() {
+                if parent != Path::new("") && !parent.is_dir() {
+                    return Err(ConfigError::CatalogDirDontExist(catalog.clone()));
+                };
+            };
+        };
+
+        if let Some(users_file) = &config.users_file {
+            if let Some(parent) = users_file.parent() {
+                if parent != Path::new("") && !parent.is_dir() {
+                    return Err(ConfigError::UserFileDirDontExist(users_file.clone()));
+                };
+            };
+        };
+
+        Ok(Self {
+            library_roots,
+            tracker_path,
+            tracker_backups: config.tracker_backups.unwrap_or(5),
+            address: config.address,
+            port: config.port,
+            enable_progress_writing: config.enable_progress_writing.unwrap_or(true),
+            static_dir,
+            duplicate_id_policy: config
+                .duplicate_id_policy
+                .unwrap_or(DuplicateIdPolicy::Fail),
+            lenient_loading: config.lenient_loading.unwrap_or(false),
+            rescan_interval: match config.rescan_interval {
+                None | Some(0) => None,
+                Some(seconds) => Some(Duration::from_secs(seconds)),
+            },
+            admin_token: config.admin_token.filter(|token| !token.is_empty()),
+            page_naming,
+            navigation_cache: config.navigation_cache,
+            scan_threads: config.scan_threads.unwrap_or(4).max(1),
+            catalog: config.catalog,
+            users_file: config.users_file,
+            secret_key: config.secret_key.filter(|key| !key.is_empty()),
+            admin_users: config.admin_users.unwrap_or_default(),
+        })
+    }
+
+    /// Check that the static files can be served. The subcommands don't need them.
+    pub fn check_static_dir(&self) -> Result<(), ConfigError> {
+        if !self.static_dir.is_dir() {
+            return Err(ConfigError::StaticDirNotADirectory(self.static_dir.clone()));
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn page_namings_are_matched_with_the_roots() {
+        let folder =
+            std::env::temp_dir().join(format!("marblecomic-config-{}", std::process::id()));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(folder.join("library")).unwrap();
+        create_dir_all(folder.join("other")).unwrap();
+        // the root written another way, like from the command line
+        let root = folder.join("other/../library");
+        let config = ConfigFile {
+            library_roots: Some(vec![root.clone()]),
+            page_naming: Some(
+                vec![(folder.join("library"), PageNaming::SingleChapter)]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..ConfigFile::default()
+        };
+        let options = MarbleOptions::from_config(config.clone()).unwrap();
+        assert_eq!(
+            options.page_naming.get(&root),
+            Some(&PageNaming::SingleChapter)
+        );
+
+        let config = ConfigFile {
+            page_naming: Some(
+                vec![(folder.join("other"), PageNaming::SingleChapter)]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..config
+        };
+        assert!(matches!(
+            MarbleOptions::from_config(config),
+            Err(ConfigError::PageNamingForUnknownRoot(_))
+        ));
+
+        remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{ComicDatabaseLoadError, GetComicNavigationError};
+
+/// A problem that caused a comic (or a whole library root) to be skipped while loading
+#[derive(Debug)]
+pub enum LoadProblem {
+    Load(ComicDatabaseLoadError),
+    /// The navigation of the comic with this id couldn't be computed
+    Navigation(usize, GetComicNavigationError),
+}
+
+impl LoadProblem {
+    pub fn error(&self) -> &(dyn Error + 'static) {
+        match self {
+            Self::Load(err) => err,
+            Self::Navigation(_, err) => err,
+        }
+    }
+
+    /// The error message, followed by the message of all the errors that caused it
+    pub fn error_chain(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        let mut current = Some(self.error());
+        while let Some(err) = current {
+            result.push(err.to_string());
+            current = err.source();
+        }
+        result
+    }
+}
+
+impl fmt::Display for LoadProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Self::Navigation(comic_id, _) = self {
+            write!(f, "comic {}: ", comic_id)?;
+        };
+        write!(f, "{}", self.error_chain().join(": "))
+    }
+}
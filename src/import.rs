@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{copy, create_dir, hard_link, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    comic_to_data, list_data_files, mime_type_from_name, natural_cmp, ChapterInfo, Comic,
+    DirectorySource, PageId, PageSource, PageSourceError, UpgradeFileError,
+};
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("can't list the files of {1}")]
+    CantListSource(#[source] PageSourceError, PathBuf),
+    #[error("no image was found in {0}")]
+    NoImage(PathBuf),
+    #[error("the destination folder {0} already exist")]
+    DestinationExist(PathBuf),
+    #[error("can't create the comic folder {1}")]
+    CantCreateFolder(#[source] io::Error, PathBuf),
+    #[error("can't copy or link {1} to {2}")]
+    CantCopyFile(#[source] io::Error, PathBuf, PathBuf),
+    #[error("can't create the data file at {1}")]
+    CantCreateDataFile(#[source] io::Error, PathBuf),
+    #[error("can't write the data file at {1}")]
+    CantWriteDataFile(#[source] serde_json::Error, PathBuf),
+}
+
+/// A file to copy into the comic folder
+#[derive(Debug, Clone)]
+pub struct ImportedFile {
+    pub source: PathBuf,
+    pub page_id: PageId,
+    /// The name in the comic folder, of the form `xxxxx-yyyyy.ext`
+    pub target_name: String,
+}
+
+/// What [`ImportPlan::execute`] will do
+#[derive(Debug, Clone)]
+pub struct ImportPlan {
+    /// The comic folder to create
+    pub destination: PathBuf,
+    /// The content of the data.json to write
+    pub comic: Comic,
+    pub files: Vec<ImportedFile>,
+}
+
+/// The images of `source`, grouped by folder (with `""` for the root), in natural order
+fn list_images_by_folder(source: &Path) -> Result<Vec<(String, Vec<String>)>, ImportError> {
+    let files = DirectorySource::new(source.to_path_buf())
+        .list_files()
+        .map_err(|err| ImportError::CantListSource(err, source.to_path_buf()))?;
+    let mut folders: Vec<(String, Vec<String>)> = Vec::new();
+    for file in files {
+        if !mime_type_from_name(&file.name).starts_with("image/") {
+            continue;
+        };
+        let folder = match file.name.rfind('/') {
+            Some(separator) => file.name[..separator].to_string(),
+            None => String::new(),
+        };
+        match folders.iter_mut().find(|(other, _)| *other == folder) {
+            Some((_, names)) => names.push(file.name),
+            None => folders.push((folder, vec![file.name])),
+        };
+    }
+    // the root folder is the empty string, so it stay first
+    folders.sort_by(|first, second| natural_cmp(&first.0, &second.0));
+    for (_, names) in &mut folders {
+        names.sort_by(|first, second| natural_cmp(first, second));
+    }
+    Ok(folders)
+}
+
+/// An id greater than every `id` declared in the data.json of the comic folders of these
+/// library roots. The comics that can't be loaded are included, so the imported comic doesn't
+/// collide with them once they are fixed.
+pub fn unused_declared_id(roots: &[PathBuf]) -> Result<usize, UpgradeFileError> {
+    let mut result = 0;
+    for data_path in list_data_files(roots)? {
+        let data_file = File::open(&data_path)
+            .map_err(|err| UpgradeFileError::CantOpenFile(err, data_path.clone()))?;
+        // the id of a file that isn't valid json can't be known
+        let data: Value = match serde_json::from_reader(BufReader::new(data_file)) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        if let Some(id) = data.get("id").and_then(Value::as_u64) {
+            result = result.max(id as usize + 1);
+        };
+    }
+    Ok(result)
+}
+
+impl ImportPlan {
+    /// Plan the import of the images in `source` as a new comic in `destination`.
+    ///
+    /// The images at the root of `source` are the first chapter, and the images of each
+    /// subfolder are the following ones (their name being used as the chapter title).
+    /// Chapters and pages are ordered by name, with the numbers ordered by value.
+    pub fn new(
+        source: &Path,
+        destination: PathBuf,
+        id: usize,
+        comic_name: Option<String>,
+        description: Option<String>,
+        keywords: HashMap<String, Vec<String>>,
+    ) -> Result<Self, ImportError> {
+        if destination.exists() {
+            return Err(ImportError::DestinationExist(destination));
+        };
+        let folders = list_images_by_folder(source)?;
+        if folders.is_empty() {
+            return Err(ImportError::NoImage(source.to_path_buf()));
+        };
+
+        let mut chapters = BTreeMap::new();
+        let mut files = Vec::new();
+        for (chapter, (folder, names)) in folders.into_iter().enumerate() {
+            if !folder.is_empty() {
+                let folder_name = folder.rsplit('/').next().unwrap_or(&folder);
+                chapters.insert(
+                    chapter,
+                    ChapterInfo {
+                        title: Some(folder_name.to_string()),
+                        ..ChapterInfo::default()
+                    },
+                );
+            };
+            for (page, name) in names.into_iter().enumerate() {
+                let extension = Path::new(&name)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or_default()
+                    .to_string();
+                files.push(ImportedFile {
+                    source: source.join(&name),
+                    page_id: PageId::new(chapter, page),
+                    target_name: format!("{:05}-{:05}.{}", chapter, page, extension),
+                });
+            }
+        }
+
+        Ok(Self {
+            destination,
+            comic: Comic {
+                id,
+                declared_id: Some(id),
+                slug: None,
+                comic_name,
+                description,
+                keywords,
+                translations: Vec::new(),
+                found: true,
+                chapters,
+                chapter_groups: Vec::new(),
+                page_naming: None,
+                page_count: None,
+            },
+            files,
+        })
+    }
+
+    /// Create the comic folder, copy (or hard link) the pages in it, and write its data.json.
+    /// The data.json is written last, so the comic isn't loaded before all its pages are there.
+    pub fn execute(&self, hard_link_files: bool) -> Result<(), ImportError> {
+        create_dir(&self.destination)
+            .map_err(|err| ImportError::CantCreateFolder(err, self.destination.clone()))?;
+        for file in &self.files {
+            let target = self.destination.join(&file.target_name);
+            let result = if hard_link_files {
+                hard_link(&file.source, &target)
+            } else {
+                copy(&file.source, &target).map(|_| ())
+            };
+            result.map_err(|err| ImportError::CantCopyFile(err, file.source.clone(), target))?;
+        }
+
+        let data_path = self.destination.join("data.json");
+        let data_file = File::create(&data_path)
+            .map_err(|err| ImportError::CantCreateDataFile(err, data_path.clone()))?;
+        comic_to_data(&self.comic)
+            .and_then(|data| serde_json::to_writer_pretty(data_file, &data))
+            .map_err(|err| ImportError::CantWriteDataFile(err, data_path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    /// An empty folder in the temporary directory, unique to this test
+    fn test_folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn plan_layout() {
+        let source = test_folder("import-layout");
+        for name in &[
+            "10.png",
+            "2.jpg",
+            "notes.txt",
+            "Chapter 10/b.png",
+            "Chapter 10/a.png",
+            "Chapter 2/1.png",
+        ] {
+            let path = source.join(name);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, "image").unwrap();
+        }
+
+        let destination = source.join("nonexistent");
+        let plan = ImportPlan::new(
+            &source,
+            destination.clone(),
+            12,
+            Some("name".to_string()),
+            None,
+            HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(plan.destination, destination);
+        assert_eq!(plan.comic.declared_id, Some(12));
+        let files: Vec<(&str, &Path)> = plan
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.target_name.as_str(),
+                    file.source.strip_prefix(&source).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("00000-00000.jpg", Path::new("2.jpg")),
+                ("00000-00001.png", Path::new("10.png")),
+                ("00001-00000.png", Path::new("Chapter 2/1.png")),
+                ("00002-00000.png", Path::new("Chapter 10/a.png")),
+                ("00002-00001.png", Path::new("Chapter 10/b.png")),
+            ]
+        );
+        assert_eq!(plan.files[4].page_id, PageId::new(2, 1));
+        assert!(!plan.comic.chapters.contains_key(&0));
+        assert_eq!(plan.comic.chapters[&1].title.as_deref(), Some("Chapter 2"));
+        assert_eq!(plan.comic.chapters[&2].title.as_deref(), Some("Chapter 10"));
+
+        assert!(matches!(
+            ImportPlan::new(
+                &source,
+                source.join("Chapter 2"),
+                0,
+                None,
+                None,
+                HashMap::new()
+            ),
+            Err(ImportError::DestinationExist(_))
+        ));
+        let empty = source.join("empty");
+        create_dir_all(&empty).unwrap();
+        assert!(matches!(
+            ImportPlan::new(&empty, destination, 0, None, None, HashMap::new()),
+            Err(ImportError::NoImage(_))
+        ));
+
+        remove_dir_all(&source).unwrap();
+    }
+
+    #[test]
+    fn unused_id_includes_the_comics_that_cant_be_loaded() {
+        let root = test_folder("import-ids");
+        for (folder, data) in &[
+            ("valid", r#"{"id": 3, "keywords": {}, "translations": []}"#),
+            ("invalid", r#"{"id": 7}"#),
+            ("not-json", "{"),
+            (
+                "slug",
+                r#"{"slug": "a-slug", "keywords": {}, "translations": []}"#,
+            ),
+        ] {
+            create_dir_all(root.join(folder)).unwrap();
+            write(root.join(folder).join("data.json"), data).unwrap();
+        }
+        assert_eq!(unused_declared_id(std::slice::from_ref(&root)).unwrap(), 8);
+        assert_eq!(unused_declared_id(&[]).unwrap(), 0);
+
+        remove_dir_all(&root).unwrap();
+    }
+}
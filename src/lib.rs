@@ -1,5 +1,69 @@
 mod comic;
-pub use comic::{Comic, ComicDatabase, ComicDatabaseLoadError};
+pub use comic::{
+    reload_shared_database, Comic, ComicChanges, ComicDatabase, ComicDatabaseLoadError, ComicEntry,
+    ComicRef, DuplicateComicId, DuplicateIdPolicy, LibraryChanges, OpenPageError,
+    RescanComicError, SharedComicDatabase,
+};
 
 mod tracker;
-pub use tracker::{Tracker, TrackerReadError};
+pub use tracker::{
+    history_path_of, Annotation, HistoryEntry, ReadingStatus, Tracker, TrackerReadError,
+    UserTrackers, KEPT_HISTORY_ENTRIES, MAX_RATING,
+};
+
+mod users;
+pub use users::{is_valid_user_name, UserStore, UserStoreError};
+
+mod config;
+pub use config::{ConfigError, ConfigFile, MarbleOptions, DEFAULT_CONFIG_FILE};
+
+mod diagnostics;
+pub use diagnostics::LoadProblem;
+
+mod check;
+pub use check::{
+    check_database, check_library, CheckIssue, CheckIssueKind, CheckReport, CheckSeverity,
+};
+
+mod archive;
+pub use archive::ArchiveError;
+
+mod page_source;
+pub use page_source::{
+    mime_type_from_name, ArchiveSource, DirectorySource, PageReader, PageSource, PageSourceError,
+    SourceFile,
+};
+
+mod page_naming;
+pub use page_naming::{natural_cmp, PageNaming, PagePattern};
+
+mod import;
+pub use import::{unused_declared_id, ImportError, ImportPlan, ImportedFile};
+
+mod schema;
+pub use schema::{
+    comic_to_data, list_data_files, migrate_comic_data, read_comic_data, schema_version,
+    upgrade_comic_data, upgrade_data_file, SchemaError, UpgradeFileError, CURRENT_SCHEMA_VERSION,
+};
+
+mod navigation_cache;
+pub use navigation_cache::{NavigationCache, NavigationCacheError};
+
+mod comic_info;
+pub use comic_info::{ComicInfo, ComicInfoError, COMIC_INFO_FILE};
+
+mod scan;
+pub use scan::{scan_library, ScanProgress};
+
+#[cfg(feature = "sqlite")]
+mod catalog;
+#[cfg(feature = "sqlite")]
+pub use catalog::{sync_catalog, sync_catalog_comics, Catalog, CatalogError};
+#[cfg(feature = "sqlite")]
+pub(crate) use catalog::CatalogState;
+
+mod navigation;
+pub use navigation::{
+    Chapter, ChapterGroup, ChapterInfo, ComicNavigation, Document, GetComicNavigationError, Page,
+    PageId,
+};
@@ -6,12 +6,40 @@ use maud::{html, Markup, DOCTYPE};
 
 use rocket_contrib::serve::StaticFiles;
 
-use rocket::{State, response::status::{NotFound, Forbidden}};
+use rocket::{State, response::status::NotFound};
+use rocket::http::{ContentType, Cookie, Cookies, Status};
+use rocket::response::{content::Content, Redirect, Stream};
+use rocket::request::{self, Form, FromRequest, Request};
+use rocket::response::status::Custom;
+use rocket::Outcome;
+use rocket::config::{Config as RocketConfig, LoggingLevel};
 
-use marblecomic::{Comic, ComicDatabase, Tracker};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use std::fs::File;
-use std::path::PathBuf;
+use marblecomic::{
+    check_library, comic_to_data, list_data_files, reload_shared_database, scan_library,
+    unused_declared_id, upgrade_data_file, Annotation, Comic, ComicDatabase, ComicInfo,
+    ComicNavigation, ComicRef, ConfigError, ConfigFile, Document, DuplicateIdPolicy, HistoryEntry,
+    ImportPlan, LoadProblem, MarbleOptions, NavigationCache, PageId, PageReader, ReadingStatus,
+    RescanComicError, ScanProgress, SharedComicDatabase, Tracker, UserStore, UserTrackers,
+    COMIC_INFO_FILE, CURRENT_SCHEMA_VERSION, DEFAULT_CONFIG_FILE, KEPT_HISTORY_ENTRIES,
+    MAX_RATING,
+};
+
+#[cfg(feature = "sqlite")]
+use marblecomic::Catalog;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
 
 fn present_page(content: Markup, title: &str) -> Markup {
     html!(
@@ -28,6 +56,8 @@ fn present_page(content: Markup, title: &str) -> Markup {
                     li { a href="/" { "main page" }}
                     li { a href="/list" { "comic list" }}
                     li { a href="/keywords" { "keywords" }}
+                    li { a href="/history" { "history" }}
+                    li { a href="/login" { "account" }}
                 }
             }
             (content)
@@ -61,77 +91,250 @@ fn present_error(error_message: &str, internal: bool) -> Markup {
     ), if internal {"internal error"} else {"error"})
 }
 
-fn is_finished(navigation: &Vec<Vec<Option<PathBuf>>>, progress: (usize, usize)) -> bool {
+fn is_finished(navigation: &ComicNavigation, progress: (usize, usize)) -> bool {
     if progress != (0, 0) {
-        if navigation.len() == progress.0 + 1 {
-            let navigation_progress_chapter = navigation.get(progress.0).unwrap();
-            if navigation_progress_chapter.len() <= progress.1 + 1 {
-                true
-            } else {
-                false
-            }
-        } else if navigation.len() < progress.0 + 1 {
-            true
-        } else {
-            false
+        match navigation.last_page() {
+            Some(last_page) => PageId::new(progress.0, progress.1) >= last_page,
+            None => true,
         }
     } else {
         false
     }
 }
 
+/// The reading status of a comic. The comics with a progress saved before the statuses existed
+/// are considered read or completed, depending on their progress.
+fn reading_status(
+    comic: &Comic,
+    tracker: &Tracker,
+    comic_database: &ComicDatabase,
+) -> Option<ReadingStatus> {
+    if let Some(status) = tracker.get_status(&comic.reference()) {
+        return Some(status);
+    };
+    let progress = tracker.get_progress(&comic, comic_database);
+    // a comic whose pages can't be read is never finished
+    let finished = matches!(
+        comic_database.get_comic_navigation(comic.id),
+        Ok(navigation) if is_finished(&navigation, progress)
+    );
+    if finished {
+        Some(ReadingStatus::Completed)
+    } else if progress != (0, 0) {
+        Some(ReadingStatus::Reading)
+    } else {
+        None
+    }
+}
+
 fn create_link_to_comic(
     comic: &Comic,
     tracker: &Tracker,
     comic_database: &ComicDatabase,
 ) -> Markup {
-    let progress = tracker.get_progress(&comic);
+    let progress = tracker.get_progress(&comic, comic_database);
     let have_progress = progress != (0, 0);
-    let navigation = comic_database.get_comic_navigation(comic.id).unwrap(); //TODO: proper error handling
-    let finished = is_finished(&navigation, progress);
+    let navigation = comic_database.get_comic_navigation(comic.id);
+    let finished = matches!(&navigation, Ok(navigation) if is_finished(navigation, progress));
+    let status = reading_status(comic, tracker, comic_database);
+    let annotation = tracker.get_annotation(&comic.reference());
     html!(
-        a href=(format!("/comic/{}", comic.id)) {
+        a href=(format!("/comic/{}", comic.reference())) {
             @if let Some(name) = &comic.comic_name {
                 (name)
             } @else {
                 "unnamed"
             }
-            @if have_progress {
-                " "
-                @if finished {
-                    "(finished)"
-                } @else {
-                    " (currently at chapter " (progress.0) " image " (progress.1) ")"
+            @if annotation.favorite {
+                " \u{2605}"
+            }
+            @if let Some(rating) = annotation.rating {
+                " [" (rating) "/" (MAX_RATING) "]"
+            }
+            @if let Some(status) = status {
+                " (" (status.label())
+                @if have_progress && !finished {
+                    ", currently at chapter " (progress.0) " image " (progress.1)
                 }
+                ")"
+            }
+            @if navigation.is_err() {
+                " (its pages can't be read)"
             }
         }
     )
 }
 
-#[get("/list")]
-fn list_comic(comic_database: State<ComicDatabase>, tracker: State<Tracker>) -> Markup {
-    present_page(
+fn present_chapter_link(comic: &ComicRef, chap_id: usize, navigation: &ComicNavigation) -> Markup {
+    let info = navigation.chapter_info(chap_id);
+    html!(
+        a href=(format!("/comic/{}/chap/{}", comic, chap_id)) {
+            "chapter " (chap_id)
+            @if let Some(title) = info.and_then(|info| info.title.as_ref()) {
+                " : " (title)
+            }
+        }
+        @if let Some(date) = info.and_then(|info| info.date.as_ref()) {
+            " (" (date) ")"
+        }
+    )
+}
+
+/// A notice shown while the library is loading, as the comics are only listed once they are read
+fn present_scan_progress(progress: &ScanProgress) -> Markup {
+    html!(
+        @if !progress.is_finished() {
+            p class="loading" {
+                "the library is still loading (" (progress.done()) " / " (progress.total())
+                " comics read), some comics may be missing."
+            }
+        }
+        @if let Some(error) = progress.error() {
+            p class="loading" {
+                "the loading of the library stopped on an error, some comics are missing: " (error)
+            }
+        }
+    )
+}
+
+/// The orders the comic list can be sorted in, by the name used in its url, and their label
+const LIST_ORDERS: [(&str, &str); 3] = [
+    ("", "library order"),
+    ("rating", "best rated first"),
+    ("favorite", "favorites first"),
+];
+
+#[get("/list?<status>&<favorite>&<min_rating>&<sort>")]
+fn list_comic(
+    comic_database: State<SharedComicDatabase>,
+    tracker: VisitorTracker,
+    progress: State<Arc<ScanProgress>>,
+    status: Option<String>,
+    favorite: Option<bool>,
+    min_rating: Option<u8>,
+    sort: Option<String>,
+) -> Result<Markup, Custom<Markup>> {
+    let comic_database = comic_database.read().unwrap();
+    // the empty values are the "all" choices of the filter form
+    let status = match status.as_deref().filter(|status| !status.is_empty()) {
+        None => None,
+        Some(status) => match ReadingStatus::from_str(status) {
+            Ok(status) => Some(status),
+            Err(err) => return Err(Custom(Status::BadRequest, present_error(&err, false))),
+        },
+    };
+    let favorite = favorite.unwrap_or(false);
+    let sort = sort.unwrap_or_default();
+    if !LIST_ORDERS.iter().any(|(name, _)| *name == sort) {
+        return Err(Custom(
+            Status::BadRequest,
+            present_error(&format!("unknown comic list order {:?}", sort), false),
+        ));
+    };
+
+    let mut comics: Vec<(&Comic, Annotation)> = comic_database
+        .comics()
+        .iter()
+        .map(|(_, entry)| {
+            (
+                &entry.comic,
+                tracker.get_annotation(&entry.comic.reference()),
+            )
+        })
+        .filter(|(comic, annotation)| {
+            comic.found
+                && (status.is_none()
+                    || reading_status(comic, &*tracker, &*comic_database) == status)
+                && (!favorite || annotation.favorite)
+                && min_rating.map_or(true, |min_rating| {
+                    annotation
+                        .rating
+                        .map_or(false, |rating| rating >= min_rating)
+                })
+        })
+        .collect();
+    match sort.as_str() {
+        // the unrated comics come last
+        "rating" => comics.sort_by_key(|(_, annotation)| Reverse(annotation.rating)),
+        "favorite" => comics.sort_by_key(|(_, annotation)| !annotation.favorite),
+        _ => (),
+    };
+
+    Ok(present_page(
         html!(
-            ul {
-                @for (_, (_, comic)) in comic_database.comics().iter() {
-                    @if comic.found {
-                        li { (create_link_to_comic(comic, &*tracker, &*comic_database)) }
+            (present_scan_progress(&progress))
+            form method="get" action="/list" {
+                label {
+                    "status "
+                    select name="status" {
+                        option value="" { "all" }
+                        @for choice in &ReadingStatus::ALL {
+                            option value=(choice.name()) selected?[status == Some(*choice)] {
+                                (choice.label())
+                            }
+                        }
                     }
                 }
+                " "
+                label {
+                    input type="checkbox" name="favorite" value="true" checked?[favorite];
+                    " favorites only"
+                }
+                " "
+                label {
+                    "minimum rating "
+                    select name="min_rating" {
+                        option value="" { "none" }
+                        @for rating in 1..=MAX_RATING {
+                            option value=(rating) selected?[min_rating == Some(rating)] { (rating) }
+                        }
+                    }
+                }
+                " "
+                label {
+                    "sort by "
+                    select name="sort" {
+                        @for (name, label) in &LIST_ORDERS {
+                            option value=(name) selected?[*name == sort] { (label) }
+                        }
+                    }
+                }
+                " "
+                input type="submit" value="filter";
+            }
+            ul {
+                @for (comic, _) in &comics {
+                    li { (create_link_to_comic(comic, &*tracker, &*comic_database)) }
+                }
             }
         ),
-        "comic list",
-    )
+        &match status {
+            Some(status) => format!("comic list ({})", status.label()),
+            None => "comic list".to_string(),
+        },
+    ))
 }
 
 #[get("/comic/<comic_id>")]
-fn display_comic_page(comic_database: State<ComicDatabase>, comic_id: usize) -> Option<Markup> {
-    let comic = if let Some(comic) = comic_database.get_comic(comic_id) {
+fn display_comic_page(
+    comic_database: State<SharedComicDatabase>,
+    tracker: VisitorTracker,
+    option: State<MarbleOptions>,
+    comic_id: String,
+) -> Result<Markup, NotFound<Markup>> {
+    let comic_database = comic_database.read().unwrap();
+    let comic = if let Some(comic) = comic_database.get_comic_by_ref(&ComicRef::from(&*comic_id)) {
         comic
     } else {
-        return None;
+        return Err(NotFound(present_error("comic not found", false)));
     };
-    Some(present_page(
+    let navigation = comic_database
+        .get_comic_navigation(comic.id)
+        .map_err(|err| NotFound(present_error(&err.to_string(), false)))?;
+    let reference = comic.reference();
+    let status = reading_status(comic, &*tracker, &*comic_database);
+    let annotation = tracker.get_annotation(&reference);
+    Ok(present_page(
         html!(
             ul {
                 @for translation in &comic.translations {
@@ -143,6 +346,67 @@ fn display_comic_page(comic_database: State<ComicDatabase>, comic_id: usize) ->
                 }
             }
 
+            @if option.enable_progress_writing {
+                form method="post" action=(format!("/set_status/{}", reference)) {
+                    label {
+                        "reading status "
+                        select name="status" {
+                            option value="none" selected?[status.is_none()] { "none" }
+                            @for choice in &ReadingStatus::ALL {
+                                option value=(choice.name()) selected?[status == Some(*choice)] {
+                                    (choice.label())
+                                }
+                            }
+                        }
+                    }
+                    " "
+                    input type="submit" value="set";
+                }
+
+                form method="post" action=(format!("/set_annotation/{}", reference)) {
+                    label {
+                        input type="checkbox" name="favorite" value="true" checked?[annotation.favorite];
+                        " favorite"
+                    }
+                    " "
+                    label {
+                        "rating "
+                        select name="rating" {
+                            option value="" selected?[annotation.rating.is_none()] { "none" }
+                            @for rating in 1..=MAX_RATING {
+                                option value=(rating) selected?[annotation.rating == Some(rating)] {
+                                    (rating) "/" (MAX_RATING)
+                                }
+                            }
+                        }
+                    }
+                    br {}
+                    label {
+                        "note"
+                        br {}
+                        textarea name="note" rows="4" cols="60" {
+                            (annotation.note.as_deref().unwrap_or(""))
+                        }
+                    }
+                    br {}
+                    input type="submit" value="save";
+                }
+            } @else {
+                @if let Some(status) = status {
+                    p { "reading status: " (status.label()) }
+                }
+                @if annotation.favorite {
+                    p { "favorite" }
+                }
+                @if let Some(rating) = annotation.rating {
+                    p { "rating: " (rating) "/" (MAX_RATING) }
+                }
+                @if let Some(note) = &annotation.note {
+                    h2 { "note" }
+                    p { (note) }
+                }
+            }
+
             @if let Some(description) = &comic.description {
                 h2 { "description" }
 
@@ -151,16 +415,26 @@ fn display_comic_page(comic_database: State<ComicDatabase>, comic_id: usize) ->
 
             h2 { "parts" }
 
-            ul {
-                @for (chap_id, _) in comic_database.get_comic_navigation(comic.id).unwrap().iter().enumerate() {
-                    li {
-                        a href=(format!("/comic/{}/chap/{}", comic.id, chap_id)) {
-                            "chapter " (chap_id)
+            @for group in navigation.groups() {
+                h3 { (group.title) }
+                @if let Some(description) = &group.description {
+                    p { (description) }
+                }
+                ul {
+                    @for chap_id in &group.chapters {
+                        @if navigation.chapter(*chap_id).map_or(false, |chapter| !chapter.is_empty()) {
+                            li { (present_chapter_link(&reference, *chap_id, &navigation)) }
                         }
                     }
                 }
             }
 
+            ul {
+                @for (chap_id, _) in navigation.ungrouped_chapters() {
+                    li { (present_chapter_link(&reference, chap_id, &navigation)) }
+                }
+            }
+
 
         ),
         if let Some(name) = &comic.comic_name {
@@ -173,42 +447,60 @@ fn display_comic_page(comic_database: State<ComicDatabase>, comic_id: usize) ->
 
 #[get("/comic/<comic_id>/chap/<chap_id>")]
 fn display_chapter_page(
-    comic_id: usize,
+    comic_id: String,
     chap_id: usize,
-    comic_database: State<ComicDatabase>,
+    comic_database: State<SharedComicDatabase>,
     options: State<MarbleOptions>,
 ) -> Result<Markup, NotFound<Markup>> {
-    let comic = if let Some(comic) = comic_database.get_comic(comic_id) {
+    let comic_database = comic_database.read().unwrap();
+    let comic = if let Some(comic) = comic_database.get_comic_by_ref(&ComicRef::from(&*comic_id)) {
         comic
     } else {
         return Err(NotFound(present_error("comic not found", false)))
     };
-    let navigation = comic_database.get_comic_navigation(comic.id).unwrap();
-    let chap_navigation = if let Some(chap_navigation) = navigation.get(chap_id) {
+    let navigation = comic_database
+        .get_comic_navigation(comic.id)
+        .map_err(|err| NotFound(present_error(&err.to_string(), false)))?;
+    let chap_navigation = if let Some(chap_navigation) = navigation.chapter(chap_id) {
         chap_navigation
     } else {
         return Err(NotFound(present_error("chapter not found", false)));
     };
 
-    let previous_chapter_id = chap_id.checked_sub(1);
-    let next_chapter_id = if let Some(_) = navigation.get(chap_id + 1) {
-        Some(chap_id + 1)
-    } else {
-        None
+    let previous_chapter_id = navigation.previous_chapter(chap_id);
+    let next_chapter_id = navigation.next_chapter(chap_id);
+    let reference = comic.reference();
+    let chapter_info = navigation.chapter_info(chap_id);
+    let chapter_name = match navigation.chapter_title(chap_id) {
+        Some(title) => format!("chap {} : {}", chap_id, title),
+        None => format!("chap {}", chap_id),
     };
 
     Ok(present_page(
         html!(
-            @for (page_id, option_path) in chap_navigation.iter().enumerate() {
-                @if let Some(file_path) = option_path {
-                    div class="page" {
-                        p { "page " (page_id) }
-                        img src=(format!("/image/comic/{}/chap/{}/{}.{}", comic.id, chap_id, page_id, file_path.extension().unwrap().to_str().unwrap())) {} //TODO: do not use unwrap
-                        @if options.enable_progress_writing {
+            @if let Some(group) = navigation.group_of(chap_id) {
+                p { "part of " (group.title) }
+            }
+            @if let Some(date) = chapter_info.and_then(|info| info.date.as_ref()) {
+                p { "published " (date) }
+            }
+            @if let Some(description) = chapter_info.and_then(|info| info.description.as_ref()) {
+                p { (description) }
+            }
+
+            @for (page_id, page) in chap_navigation.pages() {
+                div class="page" {
+                    p { "page " (page_id) }
+                    @for (index, document) in page.documents().iter().enumerate() {
+                        @if index != 0 {
                             br {}
-                            a href=(format!("/set_progress/{}/{}/{}", comic.id, chap_id, page_id)) {
-                                "set progress to this page"
-                            }
+                        }
+                        img src=(document_url(&reference, PageId::new(chap_id, page_id), index, document)) {}
+                    }
+                    @if options.enable_progress_writing {
+                        br {}
+                        form method="post" action=(format!("/set_progress/{}/{}/{}", reference, chap_id, page_id)) {
+                            input type="submit" value="set progress to this page";
                         }
                     }
                 }
@@ -216,66 +508,105 @@ fn display_chapter_page(
 
             @if let Some(previous_chapter_id) = previous_chapter_id {
                 p {
-                    a href=(format!("/comic/{}/chap/{}", comic.id, previous_chapter_id)) { "previous chapter" }
+                    a href=(format!("/comic/{}/chap/{}", reference, previous_chapter_id)) { "previous chapter" }
                 }
             }
 
             @if let Some(next_chapter_id) = next_chapter_id {
                 p {
-                    a href=(format!("/comic/{}/chap/{}", comic.id, next_chapter_id)) { "next chapter" }
+                    a href=(format!("/comic/{}/chap/{}", reference, next_chapter_id)) { "next chapter" }
                 }
             }
         ),
         (if let Some(name) = &comic.comic_name {
-            format!("{}, chap {}", name, chap_id)
+            format!("{}, {}", name, chapter_name)
         } else {
-            format!("chap {} of an unnamed comic", chap_id)
+            format!("{} of an unnamed comic", chapter_name)
         })
         .as_ref(),
     ))
 }
 
+/// The url of a document of a page: `<page>.<ext>` for the first document, and
+/// `<page>-<index>.<ext>` for the following ones
+fn document_url(comic: &ComicRef, page_id: PageId, index: usize, document: &Document) -> String {
+    let extension = document.extension().unwrap_or_default();
+    if index == 0 {
+        format!("/image/comic/{}/chap/{}/{}.{}", comic, page_id.chapter, page_id.page, extension)
+    } else {
+        format!("/image/comic/{}/chap/{}/{}-{}.{}", comic, page_id.chapter, page_id.page, index, extension)
+    }
+}
+
 #[get("/image/comic/<comic_id>/chap/<chap_id>/<page_id_and_extension>")]
 fn send_picture(
-    comic_database: State<ComicDatabase>,
-    comic_id: usize,
+    comic_database: State<SharedComicDatabase>,
+    comic_id: String,
     chap_id: usize,
     page_id_and_extension: String,
-) -> Result<File, NotFound<Markup>> {
-    //TODO: get rid of unwrap
-    let navigation = comic_database.get_comic_navigation(comic_id).unwrap();
-    let navigation_chapter = navigation.get(chap_id).unwrap();
+) -> Result<Content<Stream<PageReader>>, NotFound<Markup>> {
+    let comic_database = comic_database.read().unwrap();
+    let comic_id = comic_database
+        .resolve(&ComicRef::from(&*comic_id))
+        .ok_or_else(|| NotFound(present_error("comic not found", false)))?;
+    let navigation = comic_database
+        .get_comic_navigation(comic_id)
+        .map_err(|err| NotFound(present_error(&err.to_string(), false)))?;
 
     let page_id_and_extension_path = PathBuf::from(page_id_and_extension);
+    let invalid_page_number = || NotFound(present_error("the page number is invalid", false));
     let page_stem = page_id_and_extension_path
         .file_stem()
-        .unwrap()
-        .to_str()
-        .unwrap();
-    let page_id = page_stem.parse::<usize>().unwrap();
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(invalid_page_number)?;
+    let mut splited = page_stem.splitn(2, '-');
+    let page_id = splited
+        .next()
+        .and_then(|page_id| page_id.parse::<usize>().ok())
+        .map(|page_id| PageId::new(chap_id, page_id))
+        .ok_or_else(invalid_page_number)?;
+    let document_index = match splited.next() {
+        Some(index) => index.parse::<usize>().map_err(|_| invalid_page_number())?,
+        None => 0,
+    };
 
-    let page_path = navigation_chapter.get(page_id).unwrap().as_ref().unwrap();
-    if page_path.extension() != page_id_and_extension_path.extension() {
+    let document = navigation
+        .page(page_id)
+        .and_then(|page| page.document(document_index))
+        .ok_or_else(|| NotFound(present_error("page not found", false)))?;
+    if document.extension() != page_id_and_extension_path.extension().and_then(|x| x.to_str()) {
         Err(NotFound(present_error("the extension does not match the expected one", false)))
     } else {
-        Ok(File::open(page_path).unwrap())
+        let content_type =
+            ContentType::parse_flexible(document.mime_type()).unwrap_or(ContentType::Binary);
+        // pages stored in an archive are streamed from memory, without being extracted on the disk
+        let page = comic_database
+            .open_page(comic_id, page_id, document_index)
+            .map_err(|err| NotFound(present_error(&err.to_string(), false)))?;
+        Ok(Content(content_type, Stream::from(page)))
     }
 }
 
 #[get("/")]
 fn index(
-    tracker: State<Tracker>,
-    comic_database: State<ComicDatabase>,
+    tracker: VisitorTracker,
+    comic_database: State<SharedComicDatabase>,
+    progress: State<Arc<ScanProgress>>,
 ) -> Markup {
+    let comic_database = comic_database.read().unwrap();
     let tracked = tracker.list_comic_with_progress();
     present_page(
         html!(
+            (present_scan_progress(&progress))
             h2 { "comic with ongoing reading" }
-            @for comic_id in tracked {
-                @let comic = comic_database.get_comic(comic_id).unwrap();
-                @let navigation = comic_database.get_comic_navigation(comic.id).unwrap();
-                @if !is_finished(&navigation, tracker.get_progress(&comic)) {
-                    li { (create_link_to_comic(comic, &*tracker, &*comic_database)) }
+            // the most recently read first
+            @for reference in tracked {
+                // the comic may have been removed from the library since the progress was saved
+                @if let Some(comic) = comic_database.get_comic_by_ref(&reference) {
+                    @let status = reading_status(comic, &*tracker, &*comic_database);
+                    @if status == Some(ReadingStatus::Reading) || status == Some(ReadingStatus::Rereading) {
+                        li { (create_link_to_comic(comic, &*tracker, &*comic_database)) }
+                    }
                 }
             }
         ),
@@ -283,15 +614,114 @@ fn index(
     )
 }
 
+/// Format a time in seconds since the unix epoch, like `2020-11-15 18:02 UTC`
+fn format_timestamp(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let seconds_of_day = time % 86400;
+    // convert the number of days to a date of the proleptic gregorian calendar, with the
+    // algorithm of http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+fn present_history_entry(entry: &HistoryEntry, comic_database: &ComicDatabase) -> Markup {
+    let reference = ComicRef::from(entry.comic.as_str());
+    html!(
+        (format_timestamp(entry.time)) ": "
+        // the comic may have been removed from the library since it was read
+        @if let Some(comic) = comic_database.get_comic_by_ref(&reference) {
+            a href=(format!("/comic/{}/chap/{}", comic.reference(), entry.chapter)) {
+                @if let Some(name) = &comic.comic_name {
+                    (name)
+                } @else {
+                    "unnamed"
+                }
+            }
+        } @else {
+            (entry.comic) " (no longer in the library)"
+        }
+        " chapter " (entry.chapter) " image " (entry.image)
+    )
+}
+
+/// The number of entries on each page of the history
+const HISTORY_PAGE_SIZE: usize = 50;
+
+#[get("/history?<page>")]
+fn history(
+    tracker: VisitorTracker,
+    comic_database: State<SharedComicDatabase>,
+    page: Option<usize>,
+) -> Markup {
+    let page = page.unwrap_or(0);
+    let skip = page.saturating_mul(HISTORY_PAGE_SIZE);
+    // one more entry tell if there is an older page
+    let mut history = tracker.history(skip, HISTORY_PAGE_SIZE + 1);
+    let has_older = history.len() > HISTORY_PAGE_SIZE;
+    history.truncate(HISTORY_PAGE_SIZE);
+    let comic_database = comic_database.read().unwrap();
+    present_page(
+        html!(
+            @if history.is_empty() && page == 0 {
+                p { "nothing was read yet." }
+            }
+            ul {
+                @for entry in &history {
+                    li { (present_history_entry(entry, &comic_database)) }
+                }
+            }
+            p {
+                @if page > 0 {
+                    a href=(format!("/history?page={}", page - 1)) { "newer" }
+                    " "
+                }
+                @if has_older {
+                    a href=(format!("/history?page={}", page + 1)) { "older" }
+                } @else if skip + history.len() >= KEPT_HISTORY_ENTRIES {
+                    "the older entries are only in the "
+                    a href="/export" { "export" }
+                    "."
+                }
+            }
+        ),
+        "history",
+    )
+}
+
 #[get("/keywords")]
-fn list_keywords(comic_database: State<ComicDatabase>) -> Markup {
-    let keywords = comic_database.keywords();
+fn list_keywords(
+    comic_database: State<SharedComicDatabase>,
+    progress: State<Arc<ScanProgress>>,
+) -> Markup {
+    let comic_database = comic_database.read().unwrap();
+    let keywords = comic_database.keyword_list();
     present_page(
         html!(
+            (present_scan_progress(&progress))
             @for (keyword_section_name, keyword_section_data) in keywords {
                 h2 { (keyword_section_name) }
                 ul class="keyword_list" {
-                    @for (keyword, _) in keyword_section_data {
+                    @for keyword in keyword_section_data {
                         li class="keyword" {
                             a href=(format!("keyword/{}/{}", keyword_section_name, keyword)) {
                                 (keyword)
@@ -307,24 +737,22 @@ fn list_keywords(comic_database: State<ComicDatabase>) -> Markup {
 
 #[get("/keyword/<keyword_section>/<keyword>")]
 fn keyword_page(
-    comic_database: State<ComicDatabase>,
-    tracker: State<Tracker>,
+    comic_database: State<SharedComicDatabase>,
+    tracker: VisitorTracker,
     keyword_section: String,
     keyword: String,
 ) -> Result<Markup, NotFound<Markup>> {
-    //TODO: get rid of unwrap
-    let keywords = comic_database.keywords();
-    let keyword_comic_list = keywords
-        .get(&keyword_section)
-        .map_or(Err(NotFound(present_error("keyword section is unknown", false))), |x| Ok(x))?
-        .get(&keyword)
-        .map_or(Err(NotFound(present_error("keyword is unknwon", false))), |x| Ok(x))?;
+    let comic_database = comic_database.read().unwrap();
+    let keyword_comic_list = comic_database.comics_with_keyword(&keyword_section, &keyword);
+    if keyword_comic_list.is_empty() {
+        return Err(NotFound(present_error("keyword is unknwon", false)));
+    };
 
     Ok(present_page(
         html!(
             ul {
-                @for comic_id in keyword_comic_list {
-                    @let comic = comic_database.get_comic(*comic_id).unwrap();
+                // the catalog may still list a comic removed by a rescan that is being saved
+                @for comic in keyword_comic_list.iter().filter_map(|comic_id| comic_database.get_comic(*comic_id)) {
                     li {
                         (create_link_to_comic(&comic, &tracker, &*comic_database))
                     }
@@ -335,59 +763,1280 @@ fn keyword_page(
     ))
 }
 
-#[get("/set_progress/<comic_id>/<chapter_id>/<image_id>")]
+#[post("/set_progress/<comic_id>/<chapter_id>/<image_id>")]
 fn set_progress(
-    tracker: State<Tracker>,
+    _user: User,
+    tracker: VisitorTracker,
     option: State<MarbleOptions>,
-    comic_id: usize,
+    comic_database: State<SharedComicDatabase>,
+    comic_id: String,
     chapter_id: usize,
     image_id: usize,
-) -> Result<Markup, Forbidden<Markup>> {
+) -> Result<Redirect, Custom<Markup>> {
     if option.enable_progress_writing {
-        tracker.set_progress(comic_id, chapter_id, image_id);
-        tracker.save(&option.tracker_path).unwrap();
-        Ok(present_page(
-            html!(
-                "the progess is sucessfully save." br {}
-                a href=(format!("/comic/{}/chap/{}", comic_id, chapter_id)) {
-                    "return to this comic page"
+        // the progress is saved with the reference of the comic, whatever the one in the url
+        let reference = ComicRef::from(&*comic_id);
+        let comic_database = comic_database.read().unwrap();
+        let (reference, finished) = match comic_database.get_comic_by_ref(&reference) {
+            Some(comic) => {
+                let finished = matches!(
+                    comic_database.get_comic_navigation(comic.id),
+                    Ok(navigation) if is_finished(&navigation, (chapter_id, image_id))
+                );
+                (comic.reference(), finished)
+            }
+            None => (reference, false),
+        };
+        tracker.set_progress(&reference, chapter_id, image_id, finished);
+        save_tracker(&tracker, &option)?;
+        Ok(Redirect::to(format!("/comic/{}/chap/{}", comic_id, chapter_id)))
+    } else {
+        Err(Custom(
+            Status::Forbidden,
+            present_error("progress saving are disabled on this server", false),
+        ))
+    }
+}
+
+/// Save the tracker of the visitor, showing an internal error if it can't be saved
+fn save_tracker(tracker: &VisitorTracker, option: &MarbleOptions) -> Result<(), Custom<Markup>> {
+    tracker
+        .save(&tracker.path, option.tracker_backups)
+        .map_err(|err| {
+            print_error(&err);
+            Custom(
+                Status::InternalServerError,
+                present_error(&format!("the progress can't be saved: {}", err), true),
+            )
+        })
+}
+
+#[derive(FromForm)]
+struct StatusForm {
+    /// The name of a [`ReadingStatus`], or `none`
+    status: String,
+}
+
+#[post("/set_status/<comic_id>", data = "<form>")]
+fn set_status(
+    _user: User,
+    tracker: VisitorTracker,
+    option: State<MarbleOptions>,
+    comic_database: State<SharedComicDatabase>,
+    comic_id: String,
+    form: Form<StatusForm>,
+) -> Result<Redirect, Custom<Markup>> {
+    if !option.enable_progress_writing {
+        return Err(Custom(
+            Status::Forbidden,
+            present_error("progress saving are disabled on this server", false),
+        ));
+    };
+    let status = match form.status.as_str() {
+        "none" => None,
+        status => Some(
+            ReadingStatus::from_str(status)
+                .map_err(|err| Custom(Status::BadRequest, present_error(&err, false)))?,
+        ),
+    };
+    let comic_database = comic_database.read().unwrap();
+    let comic = comic_database
+        .get_comic_by_ref(&ComicRef::from(&*comic_id))
+        .ok_or_else(|| Custom(Status::NotFound, present_error("comic not found", false)))?;
+    tracker.set_status(&comic.reference(), status);
+    save_tracker(&tracker, &option)?;
+    Ok(Redirect::to(format!("/comic/{}", comic.reference())))
+}
+
+#[derive(FromForm)]
+struct AnnotationForm {
+    /// Unchecked checkboxes aren't sent
+    favorite: bool,
+    /// The rating, or an empty string for none
+    rating: String,
+    note: String,
+}
+
+#[post("/set_annotation/<comic_id>", data = "<form>")]
+fn set_annotation(
+    _user: User,
+    tracker: VisitorTracker,
+    option: State<MarbleOptions>,
+    comic_database: State<SharedComicDatabase>,
+    comic_id: String,
+    form: Form<AnnotationForm>,
+) -> Result<Redirect, Custom<Markup>> {
+    if !option.enable_progress_writing {
+        return Err(Custom(
+            Status::Forbidden,
+            present_error("progress saving are disabled on this server", false),
+        ));
+    };
+    let rating = match form.rating.as_str() {
+        "" => None,
+        rating => match rating.parse() {
+            Ok(rating) if (1..=MAX_RATING).contains(&rating) => Some(rating),
+            _ => {
+                return Err(Custom(
+                    Status::BadRequest,
+                    present_error(
+                        &format!("the rating should be between 1 and {}", MAX_RATING),
+                        false,
+                    ),
+                ))
+            }
+        },
+    };
+    let note = form.note.trim();
+    let annotation = Annotation {
+        favorite: form.favorite,
+        rating,
+        note: if note.is_empty() {
+            None
+        } else {
+            Some(note.to_string())
+        },
+    };
+    let comic_database = comic_database.read().unwrap();
+    let comic = comic_database
+        .get_comic_by_ref(&ComicRef::from(&*comic_id))
+        .ok_or_else(|| Custom(Status::NotFound, present_error("comic not found", false)))?;
+    tracker.set_annotation(&comic.reference(), annotation);
+    save_tracker(&tracker, &option)?;
+    Ok(Redirect::to(format!("/comic/{}", comic.reference())))
+}
+
+/// The progress, reading statuses, favorites, ratings, notes and history of the visitor, as json
+#[get("/export")]
+fn export_user_data(tracker: VisitorTracker) -> Result<Content<Vec<u8>>, Custom<Markup>> {
+    let data = tracker.export().map_err(|err| {
+        Custom(
+            Status::InternalServerError,
+            present_error(&err.to_string(), true),
+        )
+    })?;
+    Ok(Content(ContentType::JSON, data))
+}
+
+#[get("/admin/diagnostics")]
+fn diagnostics(comic_database: State<SharedComicDatabase>, _admin: AdminAccess) -> Markup {
+    let comic_database = comic_database.read().unwrap();
+    let duplicate_ids = comic_database.duplicate_ids();
+    let load_problems = comic_database.load_problems();
+    present_page(
+        html!(
+            @if duplicate_ids.is_empty() && load_problems.is_empty() {
+                p { "no problem were found while loading the library." }
+            }
+
+            @if !duplicate_ids.is_empty() {
+                h2 { "duplicate ids" }
+                ul {
+                    @for duplicate in duplicate_ids {
+                        li {
+                            "id " (duplicate.id) " : "
+                            code { (duplicate.first_path.to_string_lossy()) }
+                            " and "
+                            code { (duplicate.second_path.to_string_lossy()) }
+                        }
+                    }
+                }
+            }
+
+            @if !load_problems.is_empty() {
+                h2 { "skipped comics" }
+                ul {
+                    @for problem in load_problems {
+                        li class="diagnostic" {
+                            @if let LoadProblem::Navigation(comic_id, _) = problem {
+                                "comic " (comic_id) " : "
+                            }
+                            @for (level, message) in problem.error_chain().iter().enumerate() {
+                                @if level == 0 {
+                                    (message)
+                                } @else {
+                                    br {}
+                                    "caused by : " (message)
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        ),
+        "library diagnostics",
+    )
+}
+
+/// A request guard only succeeding if the request carry the admin token of the configuration,
+/// as an `Authorization: Bearer <token>` header, or if the visitor is logged in as one of the
+/// `admin_users`. The session cookie is only sent with the requests made from the pages of this
+/// server, so other sites can't use it to run the admin actions.
+pub struct AdminAccess;
+
+impl<'a, 'r> FromRequest<'a, 'r> for AdminAccess {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let option = request.guard::<State<MarbleOptions>>()?;
+        if option.admin_token.is_none() && option.admin_users.is_empty() {
+            return Outcome::Failure((Status::Forbidden, ()));
+        };
+        let provided_token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if let (Some(admin_token), Some(provided_token)) = (&option.admin_token, provided_token) {
+            if constant_time_eq(admin_token.as_bytes(), provided_token.as_bytes()) {
+                return Outcome::Success(AdminAccess);
+            };
+        };
+        if let Outcome::Success(User { name: Some(name) }) = request.guard::<User>() {
+            if option.admin_users.contains(&name) {
+                return Outcome::Success(AdminAccess);
+            };
+        };
+        Outcome::Failure((Status::Unauthorized, ()))
+    }
+}
+
+/// Compare two secrets in a time that only depend on their length, so the time taken to
+/// refuse a token doesn't tell how much of it is right
+fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+    first.len() == second.len()
+        && first
+            .iter()
+            .zip(second)
+            .fold(0, |difference, (first, second)| difference | (first ^ second))
+            == 0
+}
+
+fn warn_recovered_progress(path: &Path, backup: &Path) {
+    eprintln!(
+        "warning: the progress file {} is damaged, the progress was read from its backup {}",
+        path.display(),
+        backup.display()
+    );
+}
+
+/// The name of the private cookie holding the name of the logged in user
+const SESSION_COOKIE: &str = "user";
+
+/// A request guard only succeeding if the visitor is logged in. When the accounts are disabled,
+/// every visitor is accepted as an anonymous user, using the shared progress.
+pub struct User {
+    /// `None` for the anonymous user
+    name: Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for User {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let users = request.guard::<State<Option<UserStore>>>()?;
+        let users = match users.inner() {
+            Some(users) => users,
+            None => return Outcome::Success(User { name: None }),
+        };
+        let name = request
+            .cookies()
+            .get_private(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_string());
+        match name {
+            // the user may have been removed since it logged in
+            Some(name) if users.contains(&name) => Outcome::Success(User { name: Some(name) }),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// The tracker of the visitor: the one of the logged in user, or the shared one if nobody is
+/// logged in
+pub struct VisitorTracker {
+    tracker: Arc<Tracker>,
+    /// The file this tracker is saved to
+    path: PathBuf,
+}
+
+impl Deref for VisitorTracker {
+    type Target = Tracker;
+
+    fn deref(&self) -> &Tracker {
+        &self.tracker
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for VisitorTracker {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let user = match request.guard::<User>() {
+            Outcome::Success(user) => user.name,
+            _ => None,
+        };
+        let trackers = request.guard::<State<UserTrackers>>()?;
+        match trackers.get(user.as_deref()) {
+            Ok(tracker) => {
+                let path = trackers.path_of(user.as_deref());
+                if let Some(backup) = tracker.take_recovered_from() {
+                    warn_recovered_progress(&path, &backup);
+                };
+                Outcome::Success(VisitorTracker { tracker, path })
+            }
+            Err(err) => {
+                eprintln!("error: can't read the progress of {:?}: {}", user, err);
+                Outcome::Failure((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+#[derive(FromForm)]
+struct LoginForm {
+    name: String,
+    password: String,
+}
+
+#[get("/login")]
+fn login_page(
+    user: Option<User>,
+    admin: Option<AdminAccess>,
+    users: State<Option<UserStore>>,
+) -> Markup {
+    let name = user.and_then(|user| user.name);
+    present_page(
+        html!(
+            @if users.is_none() {
+                p { "the user accounts are disabled on this server, everybody share the same progress." }
+            } @else if let Some(name) = name {
+                p { "logged in as " (name) }
+                form method="post" action="/logout" {
+                    input type="submit" value="log out";
+                }
+            } @else {
+                form method="post" action="/login" {
+                    label { "name " input type="text" name="name"; }
+                    br {}
+                    label { "password " input type="password" name="password"; }
+                    br {}
+                    input type="submit" value="log in";
                 }
+            }
+            p { a href="/export" { "export your progress, reading statuses, ratings, notes and history (json)" } }
+            @if admin.is_some() {
+                p { a href="/admin/diagnostics" { "library diagnostics" } }
+            }
+        ),
+        "account",
+    )
+}
+
+#[post("/login", data = "<form>")]
+fn login(
+    form: Form<LoginForm>,
+    users: State<Option<UserStore>>,
+    mut cookies: Cookies,
+) -> Result<Redirect, Custom<Markup>> {
+    let users = users.inner().as_ref().ok_or_else(|| {
+        Custom(
+            Status::Forbidden,
+            present_error("the user accounts are disabled on this server", false),
+        )
+    })?;
+    if !users.check_password(&form.name, &form.password) {
+        return Err(Custom(
+            Status::Unauthorized,
+            present_error("unknown user name or wrong password", false),
+        ));
+    };
+    cookies.add_private(Cookie::new(SESSION_COOKIE, form.name.clone()));
+    Ok(Redirect::to("/"))
+}
+
+#[post("/logout")]
+fn logout(mut cookies: Cookies) -> Redirect {
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+    Redirect::to("/")
+}
+
+fn present_id_list(ids: &[usize]) -> Markup {
+    html!(
+        @if ids.is_empty() {
+            "none"
+        } @else {
+            @for (count, comic_id) in ids.iter().enumerate() {
+                @if count != 0 { ", " }
+                a href=(format!("/comic/{}", comic_id)) { (comic_id) }
+            }
+        }
+    )
+}
+
+/// The admin actions reloading comics would compete with the initial scan, so they wait for it
+fn check_scan_finished(progress: &ScanProgress) -> Result<(), Custom<Markup>> {
+    if let Some(error) = progress.error() {
+        Err(Custom(
+            Status::ServiceUnavailable,
+            present_error(
+                &format!(
+                    "the library couldn't be loaded, restart the server once it is fixed: {}",
+                    error
+                ),
+                false,
             ),
-            "progress saved",
         ))
+    } else if progress.is_finished() {
+        Ok(())
     } else {
-        Err(Forbidden(Some(present_error("progress saving are disabled on this server", false))))
+        Err(Custom(
+            Status::ServiceUnavailable,
+            present_error("the library is still loading, try again later", false),
+        ))
     }
 }
-pub struct MarbleOptions {
-    pub enable_progress_writing: bool,
-    pub tracker_path: PathBuf,
+
+#[post("/admin/rescan")]
+fn rescan_library(
+    _admin: AdminAccess,
+    comic_database: State<SharedComicDatabase>,
+    option: State<MarbleOptions>,
+    progress: State<Arc<ScanProgress>>,
+) -> Result<Markup, Custom<Markup>> {
+    check_scan_finished(&progress)?;
+    let changes = reload_shared_database(&comic_database, option.lenient_loading)
+        .map_err(|err| Custom(Status::InternalServerError, present_error(&err.to_string(), true)))?;
+    save_navigation_cache(&comic_database.read().unwrap());
+    sync_catalog_comics(&comic_database, &changes.changed_ids());
+
+    Ok(present_page(
+        html!(
+            ul {
+                li { "added comics : " (present_id_list(&changes.added)) }
+                li { "updated comics : " (present_id_list(&changes.updated)) }
+                li { "removed comics : " (present_id_list(&changes.removed)) }
+            }
+        ),
+        "library rescanned",
+    ))
 }
 
-fn main() {
-    let tracker_path = PathBuf::from("./progress.json");
+#[post("/admin/rescan/<comic_id>")]
+fn rescan_comic(
+    _admin: AdminAccess,
+    comic_database: State<SharedComicDatabase>,
+    progress: State<Arc<ScanProgress>>,
+    comic_id: String,
+) -> Result<Markup, Custom<Markup>> {
+    check_scan_finished(&progress)?;
+    let mut comic_database_lock = comic_database.write().unwrap();
+    let comic_id = comic_database_lock
+        .resolve(&ComicRef::from(&*comic_id))
+        .ok_or_else(|| Custom(Status::NotFound, present_error("comic not found", false)))?;
+    let changes = comic_database_lock.rescan_comic(comic_id).map_err(|err| match err {
+        RescanComicError::ComicDontExist(_) => {
+            Custom(Status::NotFound, present_error("comic not found", false))
+        }
+        err => Custom(
+            Status::InternalServerError,
+            present_error(
+                &format!("the comic {} can't be rescanned, and was left unchanged : {}", comic_id, err),
+                true,
+            ),
+        ),
+    })?;
+    save_navigation_cache(&comic_database_lock);
+    drop(comic_database_lock);
+    let mut changed_ids = vec![comic_id];
+    changed_ids.extend(changes.new_id);
+    sync_catalog_comics(&comic_database, &changed_ids);
+
+    Ok(present_page(
+        html!(
+            @if changes.removed {
+                p { "the comic doesn't exist anymore, and was removed from the library." }
+            } @else {
+                @if let Some(new_id) = changes.new_id {
+                    p { "the comic id changed to " a href=(format!("/comic/{}", new_id)) { (new_id) } }
+                }
+                @if !changes.data_changed && changes.pages_added.is_empty() && changes.pages_removed.is_empty() {
+                    p { "nothing changed." }
+                }
+                @if !changes.keywords_added.is_empty() || !changes.keywords_removed.is_empty() {
+                    h2 { "keywords" }
+                    ul {
+                        @for (category, keyword) in &changes.keywords_added {
+                            li { "added " (keyword) " (" (category) ")" }
+                        }
+                        @for (category, keyword) in &changes.keywords_removed {
+                            li { "removed " (keyword) " (" (category) ")" }
+                        }
+                    }
+                }
+                @if !changes.pages_added.is_empty() || !changes.pages_removed.is_empty() {
+                    h2 { "pages" }
+                    ul {
+                        @for page_id in &changes.pages_added {
+                            li { "added " (page_id) }
+                        }
+                        @for page_id in &changes.pages_removed {
+                            li { "removed " (page_id) }
+                        }
+                    }
+                }
+            }
+        ),
+        &format!("comic {} rescanned", comic_id),
+    ))
+}
+
+fn build_cli() -> App<'static, 'static> {
+    App::new("marblecomic")
+        .about("A simple comic reader with a reading tracker")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .value_name("FILE")
+                .help("the configuration file to use (default to ./marblecomic.toml if it exist)"),
+        )
+        .arg(
+            Arg::with_name("library")
+                .long("library")
+                .short("l")
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+                .help("a folder containing comics as subfolder. Replace the library_roots of the config file"),
+        )
+        .arg(
+            Arg::with_name("tracker")
+                .long("tracker")
+                .value_name("FILE")
+                .help("the file the reading progress is read from and saved to"),
+        )
+        .arg(
+            Arg::with_name("tracker-backups")
+                .long("tracker-backups")
+                .value_name("COUNT")
+                .validator(|count| count.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("the number of previous versions of the progress files to keep"),
+        )
+        .arg(
+            Arg::with_name("address")
+                .long("address")
+                .value_name("ADDRESS")
+                .help("the address to bind the server to"),
+        )
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .short("p")
+                .value_name("PORT")
+                .validator(|port| port.parse::<u16>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("the port to bind the server to"),
+        )
+        .arg(
+            Arg::with_name("enable-progress-writing")
+                .long("enable-progress-writing")
+                .help("allow to save the reading progress"),
+        )
+        .arg(
+            Arg::with_name("disable-progress-writing")
+                .long("disable-progress-writing")
+                .conflicts_with("enable-progress-writing")
+                .help("forbid to save the reading progress (it will still be read and displayed)"),
+        )
+        .arg(
+            Arg::with_name("static-dir")
+                .long("static-dir")
+                .value_name("DIR")
+                .help("the folder containing the static files (like marble.css)"),
+        )
+        .arg(
+            Arg::with_name("duplicate-id-policy")
+                .long("duplicate-id-policy")
+                .value_name("POLICY")
+                .possible_values(&["fail", "skip", "keep-first"])
+                .help("what to do when two comics share the same id"),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .help("skip the comics that can't be loaded instead of refusing to start"),
+        )
+        .arg(
+            Arg::with_name("rescan-interval")
+                .long("rescan-interval")
+                .value_name("SECONDS")
+                .validator(|interval| interval.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("reload the library every SECONDS seconds to see new or changed comics (0 to disable)"),
+        )
+        .arg(
+            Arg::with_name("navigation-cache")
+                .long("navigation-cache")
+                .value_name("FILE")
+                .help("the file the list of the pages of the comics is cached in, so they aren't listed again at each start"),
+        )
+        .arg(
+            Arg::with_name("users")
+                .long("users")
+                .value_name("FILE")
+                .help("the file the user accounts are stored in. If set, each user has its own progress"),
+        )
+        .arg(
+            Arg::with_name("catalog")
+                .long("catalog")
+                .value_name("FILE")
+                .help("the SQLite database the catalog of the library is stored in (needs the sqlite feature)"),
+        )
+        .arg(
+            Arg::with_name("scan-threads")
+                .long("scan-threads")
+                .value_name("COUNT")
+                .validator(|count| count.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("the number of threads reading the comics when the library is loaded at startup"),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("check that the library follow the comic specification, and print a json report of the problems found"),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("create a comic from a folder of images. Its subfolders are the chapters, and the images are ordered by name")
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .required(true)
+                        .help("the folder containing the images"),
+                )
+                .arg(
+                    Arg::with_name("into")
+                        .long("into")
+                        .value_name("DIR")
+                        .help("the library root to create the comic in (default to the first one)"),
+                )
+                .arg(
+                    Arg::with_name("folder-name")
+                        .long("folder-name")
+                        .value_name("NAME")
+                        .help("the name of the comic folder (default to the name of the source folder)"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("the name of the comic"),
+                )
+                .arg(
+                    Arg::with_name("description")
+                        .long("description")
+                        .value_name("TEXT")
+                        .help("the description of the comic"),
+                )
+                .arg(
+                    Arg::with_name("keyword")
+                        .long("keyword")
+                        .value_name("CATEGORY=KEYWORD")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(|keyword| {
+                            if keyword.contains('=') {
+                                Ok(())
+                            } else {
+                                Err("the keyword should be of the form CATEGORY=KEYWORD".into())
+                            }
+                        })
+                        .help("a keyword of the comic, can be repeated"),
+                )
+                .arg(
+                    Arg::with_name("hardlink")
+                        .long("hardlink")
+                        .help("hard link the images instead of copying them"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only print what would be done"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("rewrite the data.json of the comic folders written for an older version of marblecomic in the current format, keeping the unknown fields")
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only list the files that would be rewritten"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("user")
+                .about("manage the user accounts")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("add a user, or change its password. The password is read from the standard input")
+                        .arg(Arg::with_name("NAME").required(true).help("the name of the user")),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("remove a user (its progress is kept)")
+                        .arg(Arg::with_name("NAME").required(true).help("the name of the user")),
+                )
+                .subcommand(SubCommand::with_name("list").about("list the users")),
+        )
+        .subcommand(
+            SubCommand::with_name("export-comicinfo")
+                .about("write a ComicInfo.xml in the comic folders, from their data.json, for the other comic readers")
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("replace the existing ComicInfo.xml"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only list the files that would be written"),
+                ),
+        )
+}
+
+fn parse_command_line(matches: &ArgMatches) -> (Option<PathBuf>, ConfigFile) {
+    let config_path = matches.value_of("config").map(PathBuf::from);
 
-    let tracker = if let Ok(tracker_file) = File::open(&tracker_path) {
-        Tracker::new_from_reader(tracker_file).unwrap()
+    let enable_progress_writing = if matches.is_present("enable-progress-writing") {
+        Some(true)
+    } else if matches.is_present("disable-progress-writing") {
+        Some(false)
     } else {
-        Tracker::default()
+        None
     };
 
-    let mut comic_database = ComicDatabase::default();
-    let option = MarbleOptions {
-        enable_progress_writing: true,
-        tracker_path,
+    let cli_config = ConfigFile {
+        library_roots: matches
+            .values_of("library")
+            .map(|values| values.map(PathBuf::from).collect()),
+        tracker_path: matches.value_of("tracker").map(PathBuf::from),
+        tracker_backups: matches
+            .value_of("tracker-backups")
+            .map(|x| x.parse().unwrap()), // validated by clap
+        address: matches.value_of("address").map(|x| x.to_string()),
+        port: matches.value_of("port").map(|x| x.parse().unwrap()), // validated by clap
+        enable_progress_writing,
+        static_dir: matches.value_of("static-dir").map(PathBuf::from),
+        duplicate_id_policy: matches
+            .value_of("duplicate-id-policy")
+            .map(|x| x.parse().unwrap()), // validated by clap
+        lenient_loading: if matches.is_present("lenient") {
+            Some(true)
+        } else {
+            None
+        },
+        rescan_interval: matches
+            .value_of("rescan-interval")
+            .map(|x| x.parse().unwrap()), // validated by clap
+        // not settable from the command line, to not leak it in the process list
+        admin_token: None,
+        page_naming: None,
+        navigation_cache: matches.value_of("navigation-cache").map(PathBuf::from),
+        scan_threads: matches
+            .value_of("scan-threads")
+            .map(|x| x.parse().unwrap()), // validated by clap
+        catalog: matches.value_of("catalog").map(PathBuf::from),
+        users_file: matches.value_of("users").map(PathBuf::from),
+        // not settable from the command line, to not leak it in the process list
+        secret_key: None,
+        admin_users: None,
     };
 
-    comic_database
-        .load_from_dir(PathBuf::from("/run/media/marius/f0785b86-0e54-43be-9bb0-03da4436baec/canterlotcomics/backup"))
-        .unwrap();
+    (config_path, cli_config)
+}
+
+fn load_options(matches: &ArgMatches) -> Result<MarbleOptions, ConfigError> {
+    let (config_path, cli_config) = parse_command_line(matches);
+
+    let file_config = match config_path {
+        Some(config_path) => ConfigFile::load(&config_path)?,
+        None => {
+            let default_path = PathBuf::from(DEFAULT_CONFIG_FILE);
+            if default_path.exists() {
+                ConfigFile::load(&default_path)?
+            } else {
+                ConfigFile::default()
+            }
+        }
+    };
+
+    MarbleOptions::from_config(file_config.merge(cli_config))
+}
+
+fn print_error(error: &dyn Error) {
+    eprintln!("error: {}", error);
+    let mut source = error.source();
+    while let Some(cause) = source {
+        eprintln!("  caused by: {}", cause);
+        source = cause.source();
+    }
+}
+
+/// The rocket configuration of the active environment (set by `ROCKET_ENV`). Its `address`,
+/// `port`, `workers`, `log` and `secret_key` are read from the `[global]` and environment tables
+/// of the Rocket.toml of the current folder, and from the `ROCKET_*` environment variables, like
+/// rocket does. The options of marblecomic are applied over it.
+fn read_rocket_config() -> Result<RocketConfig, Box<dyn Error>> {
+    let mut config = RocketConfig::active()?;
+    let mut settings: HashMap<String, String> = HashMap::new();
+    match std::fs::read_to_string("Rocket.toml") {
+        Ok(content) => {
+            let mut tables: HashMap<String, HashMap<String, toml::Value>> =
+                toml::from_str(&content).map_err(|err| format!("invalid Rocket.toml: {}", err))?;
+            // the global values take precedence over the ones of the environment
+            let environment_table = tables.remove(&config.environment.to_string());
+            for table in environment_table.into_iter().chain(tables.remove("global")) {
+                for (name, value) in table {
+                    let value = match value {
+                        toml::Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    settings.insert(name, value);
+                }
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(format!("can't read Rocket.toml: {}", err).into()),
+    };
+    for name in &["address", "port", "workers", "log", "secret_key"] {
+        if let Ok(value) = std::env::var(format!("ROCKET_{}", name.to_uppercase())) {
+            settings.insert(name.to_string(), value);
+        };
+    }
+
+    let invalid = |name: &str, value: &str| format!("invalid rocket {}: {:?}", name, value);
+    for (name, value) in &settings {
+        match name.as_str() {
+            "address" => config.set_address(value.clone())?,
+            "port" => config.set_port(value.parse().map_err(|_| invalid(name, value))?),
+            "workers" => config.set_workers(value.parse().map_err(|_| invalid(name, value))?),
+            "log" => config.set_log_level(
+                value
+                    .parse::<LoggingLevel>()
+                    .map_err(|_| invalid(name, value))?,
+            ),
+            "secret_key" => config.set_secret_key(value.clone())?,
+            _ => (),
+        };
+    }
+    Ok(config)
+}
+
+fn exit_with_error(error: &dyn Error) -> ! {
+    print_error(error);
+    process::exit(1)
+}
+
+fn run_check(option: &MarbleOptions) -> ! {
+    let report = check_library(&option.library_roots, &option.page_naming);
+    let stdout = io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), &report)
+        .unwrap_or_else(|err| exit_with_error(&err));
+    println!();
+
+    eprintln!(
+        "checked {} comics: {} errors, {} warnings",
+        report.comic_count,
+        report.error_count(),
+        report.warning_count()
+    );
+    process::exit(report.exit_code())
+}
+
+fn run_import(option: &MarbleOptions, matches: &ArgMatches) -> ! {
+    let source = PathBuf::from(matches.value_of("SOURCE").unwrap()); // required by clap
+    let root = matches
+        .value_of("into")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| option.library_roots[0].clone()); // there is at least one root
+    let folder_name = match matches.value_of("folder-name") {
+        Some(folder_name) => folder_name.into(),
+        None => match source.file_name() {
+            Some(folder_name) => folder_name.to_os_string(),
+            None => {
+                eprintln!("error: can't find the name of the source folder, use --folder-name");
+                process::exit(1)
+            }
+        },
+    };
+
+    let mut keywords: HashMap<String, Vec<String>> = HashMap::new();
+    for keyword in matches.values_of("keyword").into_iter().flatten() {
+        // validated by clap
+        let mut splited = keyword.splitn(2, '=');
+        let category = splited.next().unwrap();
+        let value = splited.next().unwrap();
+        keywords
+            .entry(category.to_string())
+            .or_default()
+            .push(value.to_string());
+    }
+
+    // the comics inside archives are only found by loading the library
+    let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+    for (root, naming) in &option.page_naming {
+        comic_database.set_page_naming(root.clone(), naming.clone());
+    }
+    comic_database.load_from_dirs_lenient(&option.library_roots);
+    let id = unused_declared_id(&option.library_roots)
+        .unwrap_or_else(|err| exit_with_error(&err))
+        .max(comic_database.unused_id());
+
+    let plan = ImportPlan::new(
+        &source,
+        root.join(folder_name),
+        id,
+        matches.value_of("name").map(|x| x.to_string()),
+        matches.value_of("description").map(|x| x.to_string()),
+        keywords,
+    )
+    .unwrap_or_else(|err| exit_with_error(&err));
+
+    for file in &plan.files {
+        println!("{:?} -> {}", file.source, file.target_name);
+    }
+    if matches.is_present("dry-run") {
+        println!(
+            "would create the comic {} in {:?}, with this data.json:",
+            plan.comic.id, plan.destination
+        );
+        let data = comic_to_data(&plan.comic)
+            .and_then(|data| serde_json::to_string_pretty(&data))
+            .unwrap_or_else(|err| exit_with_error(&err));
+        println!("{}", data);
+        process::exit(0)
+    };
+    plan.execute(matches.is_present("hardlink"))
+        .unwrap_or_else(|err| exit_with_error(&err));
+    println!("created the comic {} in {:?}", plan.comic.id, plan.destination);
+    process::exit(0)
+}
+
+fn run_migrate(option: &MarbleOptions, matches: &ArgMatches) -> ! {
+    let dry_run = matches.is_present("dry-run");
+    let data_files =
+        list_data_files(&option.library_roots).unwrap_or_else(|err| exit_with_error(&err));
+
+    let mut upgraded_count = 0;
+    let mut error_count = 0;
+    for data_file in &data_files {
+        match upgrade_data_file(data_file, dry_run) {
+            Ok(CURRENT_SCHEMA_VERSION) => (),
+            Ok(version) => {
+                upgraded_count += 1;
+                println!(
+                    "{} {:?} from the schema version {} to {}",
+                    if dry_run { "would upgrade" } else { "upgraded" },
+                    data_file,
+                    version,
+                    CURRENT_SCHEMA_VERSION
+                );
+            }
+            Err(err) => {
+                error_count += 1;
+                print_error(&err);
+            }
+        };
+    }
+
+    eprintln!(
+        "{} data files checked: {} {}, {} errors",
+        data_files.len(),
+        upgraded_count,
+        if dry_run { "to upgrade" } else { "upgraded" },
+        error_count
+    );
+    process::exit(if error_count == 0 { 0 } else { 1 })
+}
+
+fn run_export_comic_info(option: &MarbleOptions, matches: &ArgMatches) -> ! {
+    let dry_run = matches.is_present("dry-run");
+    let mut comic_database = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+    for (root, naming) in &option.page_naming {
+        comic_database.set_page_naming(root.clone(), naming.clone());
+    }
+    comic_database.load_from_dirs_lenient(&option.library_roots);
+
+    let mut written_count = 0;
+    let mut error_count = 0;
+    for (comic_id, entry) in comic_database.comics() {
+        // the standalone archives can't be written to, and the comics without a data.json were
+        // loaded from their ComicInfo.xml
+        if !entry.path.join("data.json").is_file() {
+            continue;
+        };
+        let info_path = entry.path.join(COMIC_INFO_FILE);
+        if info_path.exists() && !matches.is_present("overwrite") {
+            println!("skipped {:?}, as it already exist", info_path);
+            continue;
+        };
+        let navigation = match comic_database.get_comic_navigation(comic_id) {
+            Ok(navigation) => navigation,
+            Err(err) => {
+                error_count += 1;
+                print_error(&err);
+                continue;
+            }
+        };
+        let info = ComicInfo::from_comic(&entry.comic, &navigation);
+        if dry_run {
+            println!("would write {:?}", info_path);
+        } else if let Err(err) = info.save(&info_path) {
+            error_count += 1;
+            print_error(&err);
+            continue;
+        } else {
+            println!("wrote {:?}", info_path);
+        };
+        written_count += 1;
+    }
+
+    eprintln!(
+        "{} ComicInfo.xml {}, {} errors",
+        written_count,
+        if dry_run { "to write" } else { "written" },
+        error_count
+    );
+    process::exit(if error_count == 0 { 0 } else { 1 })
+}
+
+fn run_user(option: &MarbleOptions, matches: &ArgMatches) -> ! {
+    let users_file = option
+        .users_file
+        .clone()
+        .unwrap_or_else(|| exit_with_error(&ConfigError::NoUserFile));
+    let users = UserStore::load(users_file).unwrap_or_else(|err| exit_with_error(&err));
+
+    match matches.subcommand() {
+        ("add", Some(add_matches)) => {
+            let name = add_matches.value_of("NAME").unwrap(); // required by clap
+            eprint!("password for {}: ", name);
+            let mut password = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut password)
+                .unwrap_or_else(|err| exit_with_error(&err));
+            let password = password.trim_end_matches(&['\r', '\n'][..]);
+            if password.is_empty() {
+                eprintln!("error: the password is empty");
+                process::exit(1);
+            };
+            users
+                .set_password(name, password)
+                .unwrap_or_else(|err| exit_with_error(&err));
+        }
+        ("remove", Some(remove_matches)) => {
+            let name = remove_matches.value_of("NAME").unwrap(); // required by clap
+            if !users.remove(name) {
+                eprintln!("error: there is no user named {}", name);
+                process::exit(1);
+            };
+        }
+        _ => {
+            for name in users.names() {
+                println!("{}", name);
+            }
+            process::exit(0);
+        }
+    };
+    users.save().unwrap_or_else(|err| exit_with_error(&err));
+    process::exit(0)
+}
+
+/// Save the navigation cache, if there is one. A failure is only a warning, as it is saved again
+/// after the next rescan.
+fn save_navigation_cache(comic_database: &ComicDatabase) {
+    if let Err(err) = comic_database.save_navigation_cache() {
+        eprintln!("warning: {}", err);
+    };
+}
+
+/// Copy the library to the SQLite catalog, if there is one. A failure is only a warning, as the
+/// keywords and pages are then read from the library in memory.
+#[cfg(feature = "sqlite")]
+fn sync_catalog(comic_database: &SharedComicDatabase) {
+    if let Err(err) = marblecomic::sync_catalog(comic_database) {
+        eprintln!("warning: can't update the catalog: {}", err);
+    };
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sync_catalog(_comic_database: &SharedComicDatabase) {}
+
+/// Update the rescanned comics in the SQLite catalog, if there is one
+#[cfg(feature = "sqlite")]
+fn sync_catalog_comics(comic_database: &SharedComicDatabase, comic_ids: &[usize]) {
+    if let Err(err) = marblecomic::sync_catalog_comics(comic_database, comic_ids) {
+        eprintln!("warning: can't update the comics {:?} in the catalog: {}", comic_ids, err);
+    };
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sync_catalog_comics(_comic_database: &SharedComicDatabase, _comic_ids: &[usize]) {}
+
+/// Print how many comics were loaded from each library root, and the problems found
+fn print_load_summary(comic_database: &ComicDatabase) {
+    for (root_id, root) in comic_database.roots().iter().enumerate() {
+        let comic_count = comic_database
+            .comics()
+            .values()
+            .filter(|entry| entry.root == root_id)
+            .count();
+        println!("loaded {} comics from {:?}", comic_count, root);
+    }
+    for duplicate in comic_database.duplicate_ids() {
+        eprintln!("warning: {}, ignoring it", duplicate);
+    }
+    let load_problems = comic_database.load_problems();
+    if !load_problems.is_empty() {
+        eprintln!(
+            "warning: {} problems found while loading the library (the affected comics are skipped, see /admin/diagnostics):",
+            load_problems.len()
+        );
+        for problem in load_problems {
+            eprintln!("  - {}", problem);
+        }
+    };
+}
+
+/// Load the library in the background, while the server is already running. The periodic
+/// rescan, if enabled, is started once it is loaded. If the library can't be loaded (and
+/// `lenient_loading` isn't set), the scan stops there, and the server keeps running with the
+/// comics loaded so far and the error shown on its pages.
+fn spawn_scan_thread(
+    comic_database: SharedComicDatabase,
+    progress: Arc<ScanProgress>,
+    option: MarbleOptions,
+) {
+    thread::spawn(move || {
+        let scanned = scan_library(
+            &comic_database,
+            &option.library_roots,
+            option.lenient_loading,
+            option.scan_threads,
+            &progress,
+        );
+        // the server keep running, with the error shown on its pages
+        if let Err(err) = scanned {
+            print_error(&err);
+            eprintln!(
+                "error: the library is only partly loaded, restart the server once it is fixed"
+            );
+            return;
+        };
+        {
+            let comic_database = comic_database.read().unwrap();
+            print_load_summary(&comic_database);
+            save_navigation_cache(&comic_database);
+        }
+        sync_catalog(&comic_database);
+        if let Some(rescan_interval) = option.rescan_interval {
+            spawn_rescan_thread(comic_database, rescan_interval, option.lenient_loading);
+        };
+    });
+}
+
+/// Periodically reload the library in the background, replacing the shared database once the
+/// new one is loaded
+fn spawn_rescan_thread(comic_database: SharedComicDatabase, interval: Duration, lenient: bool) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        match reload_shared_database(&comic_database, lenient) {
+            Ok(changes) => {
+                save_navigation_cache(&comic_database.read().unwrap());
+                sync_catalog_comics(&comic_database, &changes.changed_ids());
+                if !changes.is_empty() {
+                    println!(
+                        "library reloaded: added {:?}, updated {:?}, removed {:?}",
+                        changes.added, changes.updated, changes.removed
+                    );
+                };
+            }
+            Err(err) => {
+                eprintln!("warning: can't reload the library, keeping the current one: {}", err);
+            }
+        };
+    });
+}
+
+fn main() {
+    let matches = build_cli().get_matches();
+    let option = load_options(&matches).unwrap_or_else(|err| exit_with_error(&err));
+
+    match matches.subcommand() {
+        ("check", Some(_)) => run_check(&option),
+        ("import", Some(import_matches)) => run_import(&option, import_matches),
+        ("migrate", Some(migrate_matches)) => run_migrate(&option, migrate_matches),
+        ("export-comicinfo", Some(export_matches)) => run_export_comic_info(&option, export_matches),
+        ("user", Some(user_matches)) => run_user(&option, user_matches),
+        _ => (),
+    };
+
+    option
+        .check_static_dir()
+        .unwrap_or_else(|err| exit_with_error(&err));
+    let tracker = Tracker::load(&option.tracker_path).unwrap_or_else(|err| exit_with_error(&err));
+    if let Some(backup) = tracker.take_recovered_from() {
+        warn_recovered_progress(&option.tracker_path, &backup);
+    };
+    let trackers = UserTrackers::new(option.tracker_path.clone(), tracker);
+    let users = option.users_file.as_ref().map(|users_file| {
+        UserStore::load(users_file.clone()).unwrap_or_else(|err| exit_with_error(&err))
+    });
+
+    let mut comic_database = ComicDatabase::new(option.duplicate_id_policy);
+    for (root, naming) in &option.page_naming {
+        comic_database.set_page_naming(root.clone(), naming.clone());
+    }
+    if let Some(cache_path) = &option.navigation_cache {
+        let cache = NavigationCache::load(cache_path.clone()).unwrap_or_else(|err| {
+            eprintln!("warning: {}, starting with an empty cache", err);
+            NavigationCache::new(cache_path.clone())
+        });
+        comic_database.set_navigation_cache(Arc::new(cache));
+    };
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(catalog_path) = &option.catalog {
+            let catalog =
+                Catalog::open(catalog_path.clone()).unwrap_or_else(|err| exit_with_error(&err));
+            comic_database.set_catalog(Arc::new(catalog));
+        };
+    }
+
+    let mut rocket_config = read_rocket_config().unwrap_or_else(|err| exit_with_error(&*err));
+    if let Some(address) = &option.address {
+        rocket_config
+            .set_address(address.clone())
+            .unwrap_or_else(|err| exit_with_error(&err));
+    };
+    if let Some(port) = option.port {
+        rocket_config.set_port(port);
+    };
+    if let Some(secret_key) = &option.secret_key {
+        rocket_config
+            .set_secret_key(secret_key.clone())
+            .unwrap_or_else(|err| exit_with_error(&err));
+    };
+
+    let static_dir = option.static_dir.clone();
+
+    let comic_database: SharedComicDatabase = Arc::new(RwLock::new(comic_database));
+    let scan_progress = Arc::new(ScanProgress::default());
+    spawn_scan_thread(comic_database.clone(), scan_progress.clone(), option.clone());
 
-    rocket::ignite()
+    rocket::custom(rocket_config)
         .manage(comic_database)
+        .manage(scan_progress)
         .manage(option)
-        .manage(tracker)
-        .mount("/static", StaticFiles::from("static"))
+        .manage(trackers)
+        .manage(users)
+        .mount("/static", StaticFiles::from(static_dir))
         .mount(
             "/",
             routes![
@@ -398,7 +2047,17 @@ fn main() {
                 index,
                 list_keywords,
                 keyword_page,
-                set_progress
+                set_progress,
+                set_status,
+                set_annotation,
+                export_user_data,
+                history,
+                diagnostics,
+                rescan_library,
+                rescan_comic,
+                login_page,
+                login,
+                logout
             ],
         )
         .launch();
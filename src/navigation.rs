@@ -0,0 +1,692 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::archive;
+use crate::{
+    mime_type_from_name, natural_cmp, Comic, PageNaming, PagePattern, PageSource, PageSourceError,
+    SourceFile, COMIC_INFO_FILE,
+};
+
+#[derive(Error, Debug)]
+pub enum GetComicNavigationError {
+    #[error("failed to list the pages")]
+    CantListPages(#[from] PageSourceError),
+    #[error("this comic ({0}) doesn't exist")]
+    ComicDontExist(usize),
+    #[error("the file at {0} doesn't have a file name, but one is required")]
+    FileWithNoName(PathBuf),
+    #[error("the file at {0} doesn't have a stem (file name without extension)")]
+    FileWithNoStem(PathBuf),
+    #[error("can't get the {0} value of {1} when splited by '-' (count start at 0)")]
+    CantGetSplitedDash(u32, String),
+    #[error("{0} has more than 3 values when splited by '-'")]
+    TooManySplitedDash(String),
+    #[error("can't convert the value {1} from {2} to an usize")]
+    CantConvertStringFromPathToInt(ParseIntError, String, PathBuf),
+}
+
+/// Parse a `xxxxx-yyyyy.ext` or `xxxxx-yyyyy-zz.ext` name (the folders are ignored)
+fn parse_standard_name(name: &str) -> Result<(PageId, Option<usize>), GetComicNavigationError> {
+    let path = Path::new(name);
+    // the names are valid unicode, so are all their parts
+    let file_stem = path.file_stem().map_or(
+        Err(GetComicNavigationError::FileWithNoStem(path.to_path_buf())),
+        |x| Ok(x.to_str().unwrap()),
+    )?;
+
+    let mut splited = file_stem.split('-');
+    let first_part_string = splited
+        .next()
+        .ok_or_else(|| GetComicNavigationError::CantGetSplitedDash(0, file_stem.to_string()))?;
+    let first_part = parse_number(first_part_string, path)?;
+
+    let second_part_string = splited
+        .next()
+        .ok_or_else(|| GetComicNavigationError::CantGetSplitedDash(1, file_stem.to_string()))?;
+    let second_part = parse_number(second_part_string, path)?;
+
+    let third_part = match splited.next() {
+        Some(third_part_string) => Some(parse_number(third_part_string, path)?),
+        None => None,
+    };
+    if splited.next().is_some() {
+        return Err(GetComicNavigationError::TooManySplitedDash(
+            file_stem.to_string(),
+        ));
+    };
+
+    Ok((PageId::new(first_part, second_part), third_part))
+}
+
+/// Match a name with a [`PagePattern`]. Return `None` if it doesn't match.
+fn parse_with_pattern(
+    pattern: &PagePattern,
+    name: &str,
+) -> Result<Option<(PageId, Option<usize>)>, GetComicNavigationError> {
+    let captures = match pattern.regex().captures(name) {
+        Some(captures) => captures,
+        None => return Ok(None),
+    };
+    let path = Path::new(name);
+    let capture = |capture_name: &str| -> Result<Option<usize>, GetComicNavigationError> {
+        match captures.name(capture_name) {
+            Some(value) => Ok(Some(parse_number(value.as_str(), path)?)),
+            None => Ok(None),
+        }
+    };
+    let page = match capture("page")? {
+        Some(page) => page,
+        // the page group is optional in this pattern, and didn't match
+        None => return Ok(None),
+    };
+    let chapter = capture("chapter")?.unwrap_or(0);
+    Ok(Some((PageId::new(chapter, page), capture("sub_page")?)))
+}
+
+fn parse_number(value: &str, path: &Path) -> Result<usize, GetComicNavigationError> {
+    value.parse::<usize>().map_err(|err| {
+        GetComicNavigationError::CantConvertStringFromPathToInt(
+            err,
+            value.to_string(),
+            path.to_path_buf(),
+        )
+    })
+}
+
+fn is_image(name: &str) -> bool {
+    mime_type_from_name(name).starts_with("image/")
+}
+
+/// The position of a page in a comic. Pages are ordered by chapter, then by page number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PageId {
+    pub chapter: usize,
+    pub page: usize,
+}
+
+impl PageId {
+    pub fn new(chapter: usize, page: usize) -> Self {
+        Self { chapter, page }
+    }
+}
+
+impl fmt::Display for PageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chapter {} page {}", self.chapter, self.page)
+    }
+}
+
+/// A file of a [`PageSource`] displayed on a page. A page usually has a single document,
+/// but may have several (like multiple images, or alternate versions).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    name: String,
+    size: Option<u64>,
+    sub_page: Option<usize>,
+}
+
+impl Document {
+    pub fn new(name: String, size: Option<u64>, sub_page: Option<usize>) -> Self {
+        Self {
+            name,
+            size,
+            sub_page,
+        }
+    }
+
+    /// The name of the file in the [`PageSource`] of the comic
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The extension of the file, without the dot
+    pub fn extension(&self) -> Option<&str> {
+        Path::new(&self.name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+    }
+
+    /// The mime type of the document, guessed from its extension
+    pub fn mime_type(&self) -> &'static str {
+        mime_type_from_name(&self.name)
+    }
+
+    /// The size of the file, in bytes, if it is known
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The `zz` part of a `xxxxx-yyyyy-zz.ext` file name, if any
+    pub fn sub_page(&self) -> Option<usize> {
+        self.sub_page
+    }
+}
+
+/// A page of a comic, made of one or more documents
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    documents: Vec<Document>,
+}
+
+impl Page {
+    /// The documents of this page, in display order: the document without sub-page number
+    /// first, then the ones with a sub-page number, in increasing order. Documents with the
+    /// same number are ordered by name.
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    /// The document at this position in [`Page::documents`]
+    pub fn document(&self, index: usize) -> Option<&Document> {
+        self.documents.get(index)
+    }
+
+    /// The document displayed first. A page always has at least one document.
+    pub fn first_document(&self) -> &Document {
+        &self.documents[0]
+    }
+
+    fn add_document(&mut self, document: Document) {
+        let position = self
+            .documents
+            .iter()
+            .position(|other| (other.sub_page, &other.name) > (document.sub_page, &document.name))
+            .unwrap_or(self.documents.len());
+        self.documents.insert(position, document);
+    }
+}
+
+/// The optional information about a chapter, declared in the `chapters` of the data.json
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ChapterInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The publication date, as written in the data.json
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+/// A group of chapters, like an arc or a volume, declared in the `chapter_groups` of the data.json
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ChapterGroup {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The chapter numbers in this group
+    pub chapters: Vec<usize>,
+}
+
+/// The pages of a chapter. Page numbers may have gaps.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chapter {
+    pages: Vec<Option<Page>>,
+}
+
+impl Chapter {
+    pub fn page(&self, page_id: usize) -> Option<&Page> {
+        self.pages.get(page_id).and_then(|page| page.as_ref())
+    }
+
+    /// The existing pages of this chapter, in order, with their page number
+    pub fn pages(&self) -> impl Iterator<Item = (usize, &Page)> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page_id, page)| page.as_ref().map(|page| (page_id, page)))
+    }
+
+    /// The number of existing pages, not counting the gaps
+    pub fn page_count(&self) -> usize {
+        self.pages().count()
+    }
+
+    /// Return true if this chapter has no page
+    pub fn is_empty(&self) -> bool {
+        self.first_page().is_none()
+    }
+
+    pub fn first_page(&self) -> Option<usize> {
+        self.pages().next().map(|(page_id, _)| page_id)
+    }
+
+    pub fn last_page(&self) -> Option<usize> {
+        self.pages.iter().rposition(|page| page.is_some())
+    }
+
+    /// The page numbers that are lower than the last page, but have no file
+    pub fn missing_pages(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.is_none())
+            .map(|(page_id, _)| page_id)
+    }
+
+    /// Add a document to a page, creating the page if needed
+    fn add_document(&mut self, page_id: usize, document: Document) {
+        if self.pages.len() <= page_id {
+            self.pages.resize(page_id + 1, None);
+        };
+        match &mut self.pages[page_id] {
+            Some(page) => page.add_document(document),
+            page => {
+                *page = Some(Page {
+                    documents: vec![document],
+                })
+            }
+        };
+    }
+}
+
+/// The chapters and pages of a comic, as returned by
+/// [`ComicDatabase::get_comic_navigation`](crate::ComicDatabase::get_comic_navigation)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComicNavigation {
+    chapters: Vec<Chapter>,
+    chapter_infos: BTreeMap<usize, ChapterInfo>,
+    groups: Vec<ChapterGroup>,
+}
+
+impl ComicNavigation {
+    /// Find the pages of a source, naming them according to `naming`. The data.json, the
+    /// archives and the .tmp files are never pages. The chapter information and groups are
+    /// taken from `comic`.
+    pub fn from_source(
+        source: &dyn PageSource,
+        comic: &Comic,
+        naming: &PageNaming,
+    ) -> Result<Self, GetComicNavigationError> {
+        Self::from_files(source, source.list_files()?, comic, naming)
+    }
+
+    /// Like [`ComicNavigation::from_source`], with the files of the source already listed
+    pub fn from_files(
+        source: &dyn PageSource,
+        source_files: Vec<SourceFile>,
+        comic: &Comic,
+        naming: &PageNaming,
+    ) -> Result<Self, GetComicNavigationError> {
+        let mut result = Self {
+            chapter_infos: comic.chapters.clone(),
+            groups: comic.chapter_groups.clone(),
+            ..Self::default()
+        };
+
+        let mut files = Vec::new();
+        for file in source_files {
+            if Self::may_be_page(source, &file)? {
+                files.push(file);
+            };
+        }
+
+        match naming {
+            PageNaming::Standard => {
+                for file in files {
+                    let (page_id, sub_page) = parse_standard_name(&file.name)?;
+                    result.add_document(page_id, Document::new(file.name, file.size, sub_page));
+                }
+            }
+            PageNaming::Pattern { pattern } => {
+                for file in files {
+                    if let Some((page_id, sub_page)) = parse_with_pattern(pattern, &file.name)? {
+                        result.add_document(page_id, Document::new(file.name, file.size, sub_page));
+                    };
+                }
+            }
+            PageNaming::SingleChapter => {
+                let mut images: Vec<SourceFile> = files
+                    .into_iter()
+                    .filter(|file| is_image(&file.name))
+                    .collect();
+                images.sort_by(|first, second| natural_cmp(&first.name, &second.name));
+                for (page, file) in images.into_iter().enumerate() {
+                    result.add_document(
+                        PageId::new(0, page),
+                        Document::new(file.name, file.size, None),
+                    );
+                }
+            }
+            PageNaming::SubfolderPerChapter => {
+                let mut folders: Vec<(String, Vec<SourceFile>)> = Vec::new();
+                for file in files.into_iter().filter(|file| is_image(&file.name)) {
+                    // the files at the root of the source aren't in any chapter
+                    let folder = match file.name.rfind('/') {
+                        Some(separator) => file.name[..separator].to_string(),
+                        None => continue,
+                    };
+                    match folders.iter_mut().find(|(other, _)| *other == folder) {
+                        Some((_, folder_files)) => folder_files.push(file),
+                        None => folders.push((folder, vec![file])),
+                    };
+                }
+                folders.sort_by(|first, second| natural_cmp(&first.0, &second.0));
+                for (chapter, (folder, mut folder_files)) in folders.into_iter().enumerate() {
+                    folder_files.sort_by(|first, second| natural_cmp(&first.name, &second.name));
+                    for (page, file) in folder_files.into_iter().enumerate() {
+                        result.add_document(
+                            PageId::new(chapter, page),
+                            Document::new(file.name, file.size, None),
+                        );
+                    }
+                    let info = result.chapter_infos.entry(chapter).or_default();
+                    if info.title.is_none() {
+                        let folder_name = folder.rsplit('/').next().unwrap_or(&folder);
+                        info.title = Some(folder_name.to_string());
+                    };
+                }
+            }
+        };
+
+        Ok(result)
+    }
+
+    /// Return false for the files of a source that are never pages
+    fn may_be_page(
+        source: &dyn PageSource,
+        file: &SourceFile,
+    ) -> Result<bool, GetComicNavigationError> {
+        // the names are valid unicode, so are all their parts
+        let path = PathBuf::from(&file.name);
+        let file_name = path.file_name().map_or(
+            Err(GetComicNavigationError::FileWithNoName(path.clone())),
+            |x| Ok(x.to_str().unwrap()),
+        )?;
+
+        if file_name == "data.json" || file_name == COMIC_INFO_FILE {
+            return Ok(false);
+        };
+
+        if let Some(Some("tmp")) = path.extension().map(|x| x.to_str()) {
+            return Ok(false);
+        };
+
+        // the archive of a comic folder is stored alongside its data.json
+        Ok(!archive::is_archive(&source.location().join(&file.name)))
+    }
+
+    /// Add a document to the page at this position, creating the page and its chapter if needed
+    pub fn add_document(&mut self, page_id: PageId, document: Document) {
+        if self.chapters.len() <= page_id.chapter {
+            self.chapters
+                .resize_with(page_id.chapter + 1, Chapter::default);
+        };
+        self.chapters[page_id.chapter].add_document(page_id.page, document)
+    }
+
+    /// The chapter with this number. It may be empty.
+    pub fn chapter(&self, chapter_id: usize) -> Option<&Chapter> {
+        self.chapters.get(chapter_id)
+    }
+
+    /// The information about this chapter declared in the data.json, if any
+    pub fn chapter_info(&self, chapter_id: usize) -> Option<&ChapterInfo> {
+        self.chapter_infos.get(&chapter_id)
+    }
+
+    /// The title of this chapter, if one is declared in the data.json
+    pub fn chapter_title(&self, chapter_id: usize) -> Option<&str> {
+        self.chapter_info(chapter_id)
+            .and_then(|info| info.title.as_deref())
+    }
+
+    /// The arcs or volumes of this comic, in the order of the data.json
+    pub fn groups(&self) -> &[ChapterGroup] {
+        &self.groups
+    }
+
+    /// The first group that contain this chapter
+    pub fn group_of(&self, chapter_id: usize) -> Option<&ChapterGroup> {
+        self.groups
+            .iter()
+            .find(|group| group.chapters.contains(&chapter_id))
+    }
+
+    /// The chapters with at least one page that aren't part of any group
+    pub fn ungrouped_chapters(&self) -> impl Iterator<Item = (usize, &Chapter)> {
+        self.chapters()
+            .filter(move |(chapter_id, _)| self.group_of(*chapter_id).is_none())
+    }
+
+    /// The chapters that have at least one page, in order, with their chapter number
+    pub fn chapters(&self) -> impl Iterator<Item = (usize, &Chapter)> {
+        self.chapters
+            .iter()
+            .enumerate()
+            .filter(|(_, chapter)| !chapter.is_empty())
+    }
+
+    /// The number of chapters with at least one page
+    pub fn chapter_count(&self) -> usize {
+        self.chapters().count()
+    }
+
+    /// The number of pages in all the chapters
+    pub fn page_count(&self) -> usize {
+        self.chapters.iter().map(Chapter::page_count).sum()
+    }
+
+    pub fn page(&self, page_id: PageId) -> Option<&Page> {
+        self.chapter(page_id.chapter)
+            .and_then(|chapter| chapter.page(page_id.page))
+    }
+
+    /// All the existing pages, in reading order
+    pub fn pages(&self) -> impl Iterator<Item = (PageId, &Page)> {
+        self.chapters().flat_map(|(chapter_id, chapter)| {
+            chapter
+                .pages()
+                .map(move |(page_id, page)| (PageId::new(chapter_id, page_id), page))
+        })
+    }
+
+    pub fn first_page(&self) -> Option<PageId> {
+        self.pages().next().map(|(page_id, _)| page_id)
+    }
+
+    pub fn last_page(&self) -> Option<PageId> {
+        let (chapter_id, chapter) = self.chapters().last()?;
+        chapter
+            .last_page()
+            .map(|page_id| PageId::new(chapter_id, page_id))
+    }
+
+    /// The first existing page after this position, possibly in a following chapter.
+    /// The position doesn't need to be an existing page.
+    pub fn next_page(&self, page_id: PageId) -> Option<PageId> {
+        self.pages().map(|(id, _)| id).find(|id| *id > page_id)
+    }
+
+    /// The last existing page before this position, possibly in a previous chapter.
+    /// The position doesn't need to be an existing page.
+    pub fn previous_page(&self, page_id: PageId) -> Option<PageId> {
+        self.pages()
+            .map(|(id, _)| id)
+            .take_while(|id| *id < page_id)
+            .last()
+    }
+
+    /// The first chapter with a page after this chapter
+    pub fn next_chapter(&self, chapter_id: usize) -> Option<usize> {
+        self.chapters()
+            .map(|(id, _)| id)
+            .find(|id| *id > chapter_id)
+    }
+
+    /// The last chapter with a page before this chapter
+    pub fn previous_chapter(&self, chapter_id: usize) -> Option<usize> {
+        self.chapters()
+            .map(|(id, _)| id)
+            .take_while(|id| *id < chapter_id)
+            .last()
+    }
+
+    /// The chapter numbers that are lower than the last chapter, but have no page
+    pub fn missing_chapters(&self) -> impl Iterator<Item = usize> + '_ {
+        self.chapters
+            .iter()
+            .enumerate()
+            .filter(|(_, chapter)| chapter.is_empty())
+            .map(|(chapter_id, _)| chapter_id)
+    }
+
+    /// The pages missing inside of the chapters (see [`Chapter::missing_pages`]).
+    /// The pages of missing chapters aren't included.
+    pub fn missing_pages(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.chapters().flat_map(|(chapter_id, chapter)| {
+            chapter
+                .missing_pages()
+                .map(move |page_id| PageId::new(chapter_id, page_id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectorySource;
+
+    /// The navigation of a comic with these files, named with the standard naming, and these
+    /// chapter groups
+    fn navigation_of(names: &[&str], groups: &str) -> ComicNavigation {
+        let source = DirectorySource::new(PathBuf::from("/nonexistent/comic"));
+        let data = format!(
+            r#"{{"keywords": {{}}, "translations": [], "chapter_groups": {}}}"#,
+            groups
+        );
+        let comic: Comic = serde_json::from_str(&data).unwrap();
+        let files = names
+            .iter()
+            .map(|name| SourceFile {
+                name: name.to_string(),
+                size: None,
+            })
+            .collect();
+        ComicNavigation::from_files(&source, files, &comic, &PageNaming::Standard).unwrap()
+    }
+
+    /// Chapters 0, 2 and 3, with the page 1 of the chapter 0 missing, chapter 1 missing, and the
+    /// chapter 3 starting at its page 2
+    fn gapped_navigation() -> ComicNavigation {
+        navigation_of(
+            &[
+                "00000-00000.png",
+                "00000-00002.png",
+                "00002-00000.png",
+                "00002-00001-00.png",
+                "00002-00001-01.png",
+                "00003-00002.png",
+            ],
+            "[]",
+        )
+    }
+
+    fn page(chapter: usize, page: usize) -> PageId {
+        PageId::new(chapter, page)
+    }
+
+    #[test]
+    fn standard_names_are_parsed() {
+        assert_eq!(
+            parse_standard_name("00001-00002.png").unwrap(),
+            (page(1, 2), None)
+        );
+        assert_eq!(
+            parse_standard_name("folder/00001-00002-03.jpg").unwrap(),
+            (page(1, 2), Some(3))
+        );
+        assert!(matches!(
+            parse_standard_name("00001.png"),
+            Err(GetComicNavigationError::CantGetSplitedDash(1, _))
+        ));
+        assert!(matches!(
+            parse_standard_name("00001-page.png"),
+            Err(GetComicNavigationError::CantConvertStringFromPathToInt(_, _, _))
+        ));
+        assert!(matches!(
+            parse_standard_name("00001-00002-03-04.png"),
+            Err(GetComicNavigationError::TooManySplitedDash(_))
+        ));
+    }
+
+    #[test]
+    fn pages_follow_each_other_across_chapters() {
+        let navigation = gapped_navigation();
+        assert_eq!(navigation.first_page(), Some(page(0, 0)));
+        assert_eq!(navigation.last_page(), Some(page(3, 2)));
+        assert_eq!(navigation.page_count(), 5);
+        assert_eq!(navigation.chapter_count(), 3);
+        assert_eq!(navigation.page(page(2, 1)).unwrap().documents().len(), 2);
+
+        assert_eq!(navigation.next_page(page(0, 0)), Some(page(0, 2)));
+        assert_eq!(navigation.next_page(page(0, 2)), Some(page(2, 0)));
+        assert_eq!(navigation.next_page(page(2, 1)), Some(page(3, 2)));
+        assert_eq!(navigation.next_page(page(3, 2)), None);
+        // from a page that doesn't exist
+        assert_eq!(navigation.next_page(page(1, 5)), Some(page(2, 0)));
+
+        assert_eq!(navigation.previous_page(page(3, 2)), Some(page(2, 1)));
+        assert_eq!(navigation.previous_page(page(2, 0)), Some(page(0, 2)));
+        assert_eq!(navigation.previous_page(page(0, 0)), None);
+        assert_eq!(navigation.previous_page(page(3, 0)), Some(page(2, 1)));
+
+        assert_eq!(navigation.next_chapter(0), Some(2));
+        assert_eq!(navigation.previous_chapter(2), Some(0));
+        assert_eq!(navigation.next_chapter(3), None);
+    }
+
+    #[test]
+    fn gaps_are_found() {
+        let navigation = gapped_navigation();
+        assert_eq!(navigation.missing_chapters().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            navigation.missing_pages().collect::<Vec<_>>(),
+            vec![page(0, 1), page(3, 0), page(3, 1)]
+        );
+        assert_eq!(navigation.chapter(3).unwrap().last_page(), Some(2));
+        assert_eq!(navigation.chapter(3).unwrap().first_page(), Some(2));
+
+        let empty = navigation_of(&[], "[]");
+        assert_eq!(empty.first_page(), None);
+        assert_eq!(empty.last_page(), None);
+        assert_eq!(empty.missing_chapters().count(), 0);
+    }
+
+    #[test]
+    fn chapters_are_grouped() {
+        let navigation = navigation_of(
+            &[
+                "00000-00000.png",
+                "00001-00000.png",
+                "00002-00000.png",
+                "00003-00000.png",
+            ],
+            r#"[
+                {"title": "first arc", "chapters": [0, 1]},
+                {"title": "second arc", "chapters": [1, 3]}
+            ]"#,
+        );
+        let titles: Vec<&str> = navigation
+            .groups()
+            .iter()
+            .map(|group| group.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["first arc", "second arc"]);
+        // a chapter in two groups belong to the first one
+        assert_eq!(navigation.group_of(1).unwrap().title, "first arc");
+        assert_eq!(navigation.group_of(3).unwrap().title, "second arc");
+        assert!(navigation.group_of(2).is_none());
+        let ungrouped: Vec<usize> = navigation
+            .ungrouped_chapters()
+            .map(|(chapter_id, _)| chapter_id)
+            .collect();
+        assert_eq!(ungrouped, vec![2]);
+    }
+}
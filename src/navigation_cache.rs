@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{rename, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::{PageSource, PageSourceError, SourceFile};
+
+#[derive(Error, Debug)]
+pub enum NavigationCacheError {
+    #[error("can't open the navigation cache at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't parse the navigation cache at {1}")]
+    CantParseFile(#[source] serde_json::Error, PathBuf),
+    #[error("can't write the navigation cache at {1}")]
+    CantWriteFile(#[source] io::Error, PathBuf),
+}
+
+/// The files of a page source, as they were when it was last modified
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedListing {
+    modified: SystemTime,
+    /// The subfolders the files were listed from, whose modification time is checked instead
+    /// of searching the source for subfolders again
+    folders: Vec<String>,
+    files: Vec<SourceFile>,
+}
+
+#[derive(Default, Debug)]
+struct CacheContent {
+    listings: HashMap<PathBuf, CachedListing>,
+    /// True if the listings changed since they were loaded or saved
+    changed: bool,
+}
+
+/// The files of the page sources of the comics, saved to a file so they don't have to be listed
+/// again after a restart. The listing of a source is only reused if its modification time didn't
+/// change since it was cached. Only the source and the subfolders it had when it was listed are
+/// checked, so nothing is listed again for the unchanged sources.
+///
+/// The navigation of the comics is computed from those listings, so it follow the changes of
+/// the data.json and of the page naming.
+#[derive(Debug)]
+pub struct NavigationCache {
+    path: PathBuf,
+    content: Mutex<CacheContent>,
+}
+
+impl NavigationCache {
+    /// An empty cache, to be saved at `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            content: Mutex::new(CacheContent::default()),
+        }
+    }
+
+    /// Read the cache saved at `path`. The cache is empty if there is no such file.
+    pub fn load(path: PathBuf) -> Result<Self, NavigationCacheError> {
+        if !path.exists() {
+            return Ok(Self::new(path));
+        };
+        let file = File::open(&path)
+            .map_err(|err| NavigationCacheError::CantOpenFile(err, path.clone()))?;
+        let listings = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| NavigationCacheError::CantParseFile(err, path.clone()))?;
+        Ok(Self {
+            path,
+            content: Mutex::new(CacheContent {
+                listings,
+                changed: false,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The files of the source at `location`, if they were cached when it had this
+    /// modification time
+    pub fn get(&self, location: &Path, modified: SystemTime) -> Option<Vec<SourceFile>> {
+        let content = self.content.lock().unwrap();
+        content
+            .listings
+            .get(location)
+            .filter(|listing| listing.modified == modified)
+            .map(|listing| listing.files.clone())
+    }
+
+    pub fn insert(
+        &self,
+        location: PathBuf,
+        modified: SystemTime,
+        files: Vec<SourceFile>,
+        folders: Vec<String>,
+    ) {
+        let mut content = self.content.lock().unwrap();
+        content.listings.insert(
+            location,
+            CachedListing {
+                modified,
+                folders,
+                files,
+            },
+        );
+        content.changed = true;
+    }
+
+    /// The subfolders the source at `location` had when it was cached
+    fn folders(&self, location: &Path) -> Option<Vec<String>> {
+        let content = self.content.lock().unwrap();
+        content
+            .listings
+            .get(location)
+            .map(|listing| listing.folders.clone())
+    }
+
+    /// Forget the sources that aren't in `locations`, like the ones of removed comics
+    pub fn retain(&self, locations: &HashSet<&Path>) {
+        let mut content = self.content.lock().unwrap();
+        let count_before = content.listings.len();
+        content
+            .listings
+            .retain(|location, _| locations.contains(location.as_path()));
+        if content.listings.len() != count_before {
+            content.changed = true;
+        };
+    }
+
+    /// Write the cache to its file, if it changed. The content is written to a temporary file
+    /// first, so an interrupted save doesn't leave a truncated cache.
+    pub fn save(&self) -> Result<(), NavigationCacheError> {
+        let mut content = self.content.lock().unwrap();
+        if !content.changed {
+            return Ok(());
+        };
+
+        let mut temporary_path = self.path.as_os_str().to_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let write_temporary_file = || -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&temporary_path)?);
+            serde_json::to_writer(&mut writer, &content.listings)?;
+            writer.flush()
+        };
+        write_temporary_file()
+            .map_err(|err| NavigationCacheError::CantWriteFile(err, temporary_path.clone()))?;
+        rename(&temporary_path, &self.path)
+            .map_err(|err| NavigationCacheError::CantWriteFile(err, self.path.clone()))?;
+        content.changed = false;
+        Ok(())
+    }
+}
+
+/// The modification time of `source`. If it is in the cache, only the folders it had when it
+/// was listed are checked, instead of searching the whole source for subfolders.
+pub(crate) fn source_modified(
+    cache: Option<&NavigationCache>,
+    source: &dyn PageSource,
+) -> Option<SystemTime> {
+    cache
+        .and_then(|cache| cache.folders(source.location()))
+        .and_then(|folders| source.folders_modified(&folders))
+        .or_else(|| source.source_modified())
+}
+
+/// The files of `source`, taken from the cache if it has them for this modification time, or
+/// listed and added to the cache otherwise. Without a cache or a modification time, the files
+/// are always listed.
+pub(crate) fn list_source_files(
+    cache: Option<&NavigationCache>,
+    source: &dyn PageSource,
+    modified: Option<SystemTime>,
+) -> Result<Vec<SourceFile>, PageSourceError> {
+    let (cache, modified) = match (cache, modified) {
+        (Some(cache), Some(modified)) => (cache, modified),
+        _ => return source.list_files(),
+    };
+    let location = source.location();
+    if let Some(files) = cache.get(location, modified) {
+        return Ok(files);
+    };
+    let (files, folders) = source.list_files_and_folders()?;
+    cache.insert(location.to_path_buf(), modified, files.clone(), folders);
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::{DirectorySource, PageReader};
+
+    /// A source with a single page, whose modification time is set by the test, and that
+    /// count how many times it is listed
+    #[derive(Debug)]
+    struct CountingSource {
+        location: PathBuf,
+        modified: Mutex<SystemTime>,
+        list_count: AtomicUsize,
+    }
+
+    impl CountingSource {
+        fn new() -> Self {
+            Self {
+                location: PathBuf::from("/comics/counting"),
+                modified: Mutex::new(SystemTime::UNIX_EPOCH),
+                list_count: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl PageSource for CountingSource {
+        fn location(&self) -> &Path {
+            &self.location
+        }
+
+        fn list_files(&self) -> Result<Vec<SourceFile>, PageSourceError> {
+            self.list_count.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![SourceFile {
+                name: "00000-00000.png".to_string(),
+                size: Some(1),
+            }])
+        }
+
+        fn open(&self, name: &str) -> Result<PageReader, PageSourceError> {
+            Err(PageSourceError::CantOpenFile(
+                io::ErrorKind::NotFound.into(),
+                self.location.join(name),
+            ))
+        }
+
+        fn size(&self, _name: &str) -> Result<u64, PageSourceError> {
+            Ok(1)
+        }
+
+        fn modified(&self, _name: &str) -> Option<SystemTime> {
+            None
+        }
+
+        fn source_modified(&self) -> Option<SystemTime> {
+            Some(*self.modified.lock().unwrap())
+        }
+    }
+
+    /// A folder unique to this test
+    fn test_folder(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn list(cache: &NavigationCache, source: &dyn PageSource) -> Vec<String> {
+        let modified = source_modified(Some(cache), source);
+        let mut names: Vec<String> = list_source_files(Some(cache), source, modified)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn unchanged_sources_are_listed_once() {
+        let folder = test_folder("cache-reuse");
+        let cache_path = folder.join("navigation-cache.json");
+        let source = CountingSource::new();
+
+        let cache = NavigationCache::new(cache_path.clone());
+        assert_eq!(list(&cache, &source), vec!["00000-00000.png"]);
+        assert_eq!(list(&cache, &source), vec!["00000-00000.png"]);
+        assert_eq!(source.list_count.load(Ordering::SeqCst), 1);
+        cache.save().unwrap();
+
+        // after a restart, the saved listing is used
+        let cache = NavigationCache::load(cache_path).unwrap();
+        assert_eq!(list(&cache, &source), vec!["00000-00000.png"]);
+        assert_eq!(source.list_count.load(Ordering::SeqCst), 1);
+
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn changed_sources_are_listed_again() {
+        let cache = NavigationCache::new(PathBuf::from("navigation-cache.json"));
+        let source = CountingSource::new();
+
+        list(&cache, &source);
+        *source.modified.lock().unwrap() += Duration::from_secs(1);
+        list(&cache, &source);
+        assert_eq!(source.list_count.load(Ordering::SeqCst), 2);
+        list(&cache, &source);
+        assert_eq!(source.list_count.load(Ordering::SeqCst), 2);
+
+        // without a modification time, the source can't be cached
+        let cache = NavigationCache::new(PathBuf::from("navigation-cache.json"));
+        list_source_files(Some(&cache), &source, None).unwrap();
+        list_source_files(Some(&cache), &source, None).unwrap();
+        assert_eq!(source.list_count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn changes_in_subfolders_invalidate_the_listing() {
+        let folder = test_folder("cache-subfolders");
+        create_dir_all(folder.join("chapter 1")).unwrap();
+        create_dir_all(folder.join("chapter 2")).unwrap();
+        write(folder.join("chapter 1/page 1.png"), b"1").unwrap();
+        let source = DirectorySource::new(folder.clone());
+        let cache = NavigationCache::new(folder.join("navigation-cache.json"));
+
+        assert_eq!(list(&cache, &source), vec!["chapter 1/page 1.png"]);
+        // the modification times only change once the clock moved
+        thread::sleep(Duration::from_millis(50));
+        write(folder.join("chapter 1/page 2.png"), b"2").unwrap();
+        assert_eq!(
+            list(&cache, &source),
+            vec!["chapter 1/page 1.png", "chapter 1/page 2.png"]
+        );
+        // the empty subfolders are checked too
+        thread::sleep(Duration::from_millis(50));
+        write(folder.join("chapter 2/page 1.png"), b"3").unwrap();
+        assert_eq!(
+            list(&cache, &source),
+            vec![
+                "chapter 1/page 1.png",
+                "chapter 1/page 2.png",
+                "chapter 2/page 1.png"
+            ]
+        );
+
+        remove_dir_all(&folder).unwrap();
+    }
+}
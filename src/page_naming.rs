@@ -0,0 +1,262 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// How the files of a comic are mapped to chapters and pages. It can be set for a whole
+/// library root in the configuration, or for a single comic in its data.json.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum PageNaming {
+    /// `xxxxx-yyyyy.ext` or `xxxxx-yyyyy-zz.ext`, as described in the README
+    Standard,
+    /// The path of the file inside the comic is matched with a regular expression, using the
+    /// named captures `page`, and optionally `chapter` (0 if absent) and `sub_page`.
+    /// Files that don't match are ignored.
+    Pattern { pattern: PagePattern },
+    /// Every image is a page of the chapter 0, in natural order of their path
+    SingleChapter,
+    /// Every subfolder is a chapter, and the images in it are its pages. Both are in natural
+    /// order, and the name of the subfolder is used as the default chapter title.
+    SubfolderPerChapter,
+}
+
+/// A regular expression with at least a `page` named capture
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct PagePattern(Regex);
+
+impl PagePattern {
+    pub fn regex(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for PagePattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        let regex = Regex::new(&pattern).map_err(|err| err.to_string())?;
+        if !regex.capture_names().any(|name| name == Some("page")) {
+            return Err(format!(
+                "the page pattern {:?} doesn't have a \"page\" named capture",
+                pattern
+            ));
+        };
+        Ok(Self(regex))
+    }
+}
+
+impl From<PagePattern> for String {
+    fn from(pattern: PagePattern) -> Self {
+        pattern.0.as_str().to_string()
+    }
+}
+
+impl PartialEq for PagePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl fmt::Display for PagePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+/// Compare two strings so that the numbers they contain are ordered by value, like
+/// `page2.png` < `page10.png`. Letters are compared case insensitively.
+pub fn natural_cmp(first: &str, second: &str) -> Ordering {
+    let mut first_chars = first.chars().peekable();
+    let mut second_chars = second.chars().peekable();
+    loop {
+        match (first_chars.peek(), second_chars.peek()) {
+            (None, None) => return first.cmp(second),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(first_char), Some(second_char))
+                if first_char.is_ascii_digit() && second_char.is_ascii_digit() =>
+            {
+                let first_number = take_number(&mut first_chars);
+                let second_number = take_number(&mut second_chars);
+                // compare by length first, so numbers of any size can be compared
+                let ordering = first_number
+                    .len()
+                    .cmp(&second_number.len())
+                    .then_with(|| first_number.cmp(&second_number));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                };
+            }
+            (Some(first_char), Some(second_char)) => {
+                let ordering = first_char.to_lowercase().cmp(second_char.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                };
+                first_chars.next();
+                second_chars.next();
+            }
+        };
+    }
+}
+
+/// Consume the digits at the start of `chars`, and return them without leading zeros
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.peek().filter(|char| char.is_ascii_digit()) {
+        if !(number.is_empty() && *digit == '0') {
+            number.push(*digit);
+        };
+        chars.next();
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Comic, ComicNavigation, DirectorySource, SourceFile};
+
+    use std::path::PathBuf;
+
+    fn pattern(pattern: &str) -> PageNaming {
+        PageNaming::Pattern {
+            pattern: PagePattern::try_from(pattern.to_string()).unwrap(),
+        }
+    }
+
+    /// The `(chapter, page, first document)` of every page found with this naming
+    fn pages_of(naming: &PageNaming, names: &[&str]) -> Vec<(usize, usize, String)> {
+        let source = DirectorySource::new(PathBuf::from("/nonexistent/comic"));
+        let comic: Comic = serde_json::from_str(r#"{"keywords": {}, "translations": []}"#).unwrap();
+        let files = names
+            .iter()
+            .map(|name| SourceFile {
+                name: name.to_string(),
+                size: None,
+            })
+            .collect();
+        let navigation = ComicNavigation::from_files(&source, files, &comic, naming).unwrap();
+        navigation
+            .chapters()
+            .flat_map(|(chapter_id, chapter)| {
+                chapter.pages().map(move |(page_id, page)| {
+                    (
+                        chapter_id,
+                        page_id,
+                        page.first_document().name().to_string(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pattern_needs_a_page_capture() {
+        assert!(PagePattern::try_from(r"(?P<page>\d+)\.png".to_string()).is_ok());
+        assert!(PagePattern::try_from(r"(?P<number>\d+)\.png".to_string()).is_err());
+        assert!(PagePattern::try_from(r"(?P<page>\d+".to_string()).is_err());
+    }
+
+    #[test]
+    fn pattern_naming() {
+        let naming = pattern(r"ch(?P<chapter>\d+)/p(?P<page>\d+)\.jpg");
+        let pages = pages_of(
+            &naming,
+            &["ch1/p2.jpg", "ch0/p0.jpg", "cover.jpg", "ch1/p0.jpg"],
+        );
+        assert_eq!(
+            pages,
+            vec![
+                (0, 0, "ch0/p0.jpg".to_string()),
+                (1, 0, "ch1/p0.jpg".to_string()),
+                (1, 2, "ch1/p2.jpg".to_string()),
+            ]
+        );
+
+        // without a chapter capture, every page is in the chapter 0
+        let pages = pages_of(&pattern(r"(?P<page>\d+)\.png"), &["3.png", "1.png"]);
+        assert_eq!(
+            pages,
+            vec![(0, 1, "1.png".to_string()), (0, 3, "3.png".to_string())]
+        );
+    }
+
+    #[test]
+    fn single_chapter_naming() {
+        let pages = pages_of(
+            &PageNaming::SingleChapter,
+            &["page10.png", "notes.txt", "page2.png", "page1.png"],
+        );
+        assert_eq!(
+            pages,
+            vec![
+                (0, 0, "page1.png".to_string()),
+                (0, 1, "page2.png".to_string()),
+                (0, 2, "page10.png".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn subfolder_per_chapter_naming() {
+        let pages = pages_of(
+            &PageNaming::SubfolderPerChapter,
+            &[
+                "Chapter 10/b.png",
+                "Chapter 2/2.png",
+                "cover.png",
+                "Chapter 2/10.png",
+                "Chapter 10/a.png",
+            ],
+        );
+        assert_eq!(
+            pages,
+            vec![
+                (0, 0, "Chapter 2/2.png".to_string()),
+                (0, 1, "Chapter 2/10.png".to_string()),
+                (1, 0, "Chapter 10/a.png".to_string()),
+                (1, 1, "Chapter 10/b.png".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn subfolder_name_is_the_default_chapter_title() {
+        let source = DirectorySource::new(PathBuf::from("/nonexistent/comic"));
+        let comic: Comic = serde_json::from_str(
+            r#"{"keywords": {}, "translations": [], "chapters": {"1": {"title": "The end"}}}"#,
+        )
+        .unwrap();
+        let files = vec![
+            SourceFile {
+                name: "volume/first/1.png".to_string(),
+                size: None,
+            },
+            SourceFile {
+                name: "volume/second/1.png".to_string(),
+                size: None,
+            },
+        ];
+        let navigation =
+            ComicNavigation::from_files(&source, files, &comic, &PageNaming::SubfolderPerChapter)
+                .unwrap();
+        assert_eq!(navigation.chapter_title(0), Some("first"));
+        assert_eq!(navigation.chapter_title(1), Some("The end"));
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("page2.png", "page10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("page010.png", "page9.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("Page1", "page2"), Ordering::Less);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("12345678901234567890", "9"), Ordering::Greater);
+        // the same number with different leading zeros is still ordered, so sorting is stable
+        assert_ne!(natural_cmp("page01", "page1"), Ordering::Equal);
+        assert_eq!(natural_cmp("page1", "page1"), Ordering::Equal);
+    }
+}
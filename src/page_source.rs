@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{metadata, read_dir, File};
+use std::io;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use thiserror::Error;
+
+use crate::archive::Archive;
+use crate::ArchiveError;
+
+/// A reader over the content of a page
+pub type PageReader = Box<dyn Read + Send>;
+
+#[derive(Error, Debug)]
+pub enum PageSourceError {
+    #[error("failed to list sub content of {1}")]
+    CantReadDirectory(#[source] io::Error, PathBuf),
+    #[error("failed to read an entry of the content of the director {1}")]
+    CantReadDirEntry(#[source] io::Error, PathBuf),
+    #[error("the file name {0:?} in {1} isn't valid unicode")]
+    NonUnicodeFileName(OsString, PathBuf),
+    #[error("can't open the page file at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't read the metadata of the page file at {1}")]
+    CantReadMetadata(#[source] io::Error, PathBuf),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+}
+
+/// A file of a [`PageSource`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// The name of the file inside the source
+    pub name: String,
+    /// The size of the file, in bytes, if it is known
+    pub size: Option<u64>,
+}
+
+/// The place where the pages of a comic are stored.
+///
+/// Pages are identified by their name inside the source, which is the file name for a
+/// directory, or the entry name (that may contain folders) for an archive.
+pub trait PageSource: fmt::Debug + Send + Sync {
+    /// The path of the directory or archive, for display purpose
+    fn location(&self) -> &Path;
+
+    /// All the files in this source, including the ones in subfolders (with a name of the
+    /// form `folder/file`). Not all of them are necessarily pages.
+    fn list_files(&self) -> Result<Vec<SourceFile>, PageSourceError>;
+
+    /// The files of this source, like [`PageSource::list_files`], with the subfolders they were
+    /// listed from (including the empty ones)
+    fn list_files_and_folders(&self) -> Result<(Vec<SourceFile>, Vec<String>), PageSourceError> {
+        Ok((self.list_files()?, Vec::new()))
+    }
+
+    /// Open a page for reading
+    fn open(&self, name: &str) -> Result<PageReader, PageSourceError>;
+
+    /// The size of a page, in bytes
+    fn size(&self, name: &str) -> Result<u64, PageSourceError>;
+
+    /// The modification time of a page, if it is known
+    fn modified(&self, name: &str) -> Option<SystemTime>;
+
+    /// The modification time of the whole source. It change when pages are added or removed.
+    fn source_modified(&self) -> Option<SystemTime>;
+
+    /// The latest modification time of the source and of these subfolders, as returned by
+    /// [`PageSource::list_files_and_folders`]. Unlike [`PageSource::source_modified`], the
+    /// source isn't searched for other subfolders, so it only change when pages are added or
+    /// removed if these are still all the subfolders.
+    fn folders_modified(&self, _folders: &[String]) -> Option<SystemTime> {
+        self.source_modified()
+    }
+
+    /// The mime type of a page, guessed from its extension
+    fn mime_type(&self, name: &str) -> &'static str {
+        mime_type_from_name(name)
+    }
+}
+
+/// Guess the mime type of an image from the extension of its name
+pub fn mime_type_from_name(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        Some("svg") => "image/svg+xml",
+        Some("avif") => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Pages stored as files in a directory
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    path: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl PageSource for DirectorySource {
+    fn location(&self) -> &Path {
+        &self.path
+    }
+
+    fn list_files(&self) -> Result<Vec<SourceFile>, PageSourceError> {
+        Ok(self.list_files_and_folders()?.0)
+    }
+
+    fn list_files_and_folders(&self) -> Result<(Vec<SourceFile>, Vec<String>), PageSourceError> {
+        let mut files = Vec::new();
+        let mut folders = Vec::new();
+        let mut ancestors = self.path.canonicalize().into_iter().collect();
+        list_files_in(&self.path, "", &mut ancestors, &mut files, &mut folders)?;
+        Ok((files, folders))
+    }
+
+    fn open(&self, name: &str) -> Result<PageReader, PageSourceError> {
+        let path = self.path.join(name);
+        let file = File::open(&path).map_err(|err| PageSourceError::CantOpenFile(err, path))?;
+        Ok(Box::new(file))
+    }
+
+    fn size(&self, name: &str) -> Result<u64, PageSourceError> {
+        let path = self.path.join(name);
+        metadata(&path)
+            .map(|meta| meta.len())
+            .map_err(|err| PageSourceError::CantReadMetadata(err, path))
+    }
+
+    fn modified(&self, name: &str) -> Option<SystemTime> {
+        metadata(self.path.join(name))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// The latest modification time of the directory and its subfolders
+    fn source_modified(&self) -> Option<SystemTime> {
+        latest_modification(&self.path)
+    }
+
+    fn folders_modified(&self, folders: &[String]) -> Option<SystemTime> {
+        let mut latest = folder_modified(&self.path)?;
+        for folder in folders {
+            latest = latest.max(folder_modified(&self.path.join(folder))?);
+        }
+        Some(latest)
+    }
+}
+
+/// Add the files in `folder` and its subfolders to `files`, and the subfolders to `folders`,
+/// with their name prefixed by `prefix`
+fn list_files_in(
+    folder: &Path,
+    prefix: &str,
+    ancestors: &mut Vec<PathBuf>,
+    files: &mut Vec<SourceFile>,
+    folders: &mut Vec<String>,
+) -> Result<(), PageSourceError> {
+    let entries = read_dir(folder)
+        .map_err(|err| PageSourceError::CantReadDirectory(err, folder.to_path_buf()))?;
+    for entry in entries {
+        let file_name = entry
+            .map_err(|err| PageSourceError::CantReadDirEntry(err, folder.to_path_buf()))?
+            .file_name();
+        let file_name = file_name.into_string().map_err(|file_name| {
+            PageSourceError::NonUnicodeFileName(file_name, folder.to_path_buf())
+        })?;
+        let name = format!("{}{}", prefix, file_name);
+        // follow symbolic links, so the size is the one of the page
+        let meta = metadata(folder.join(&file_name)).ok();
+        if matches!(&meta, Some(meta) if meta.is_dir()) {
+            let subfolder = folder.join(&file_name);
+            // a symbolic link to a folder containing it would be listed endlessly
+            let canonical = match subfolder.canonicalize() {
+                Ok(canonical) if !ancestors.contains(&canonical) => canonical,
+                _ => continue,
+            };
+            ancestors.push(canonical);
+            list_files_in(&subfolder, &format!("{}/", name), ancestors, files, folders)?;
+            ancestors.pop();
+            folders.push(name);
+        } else {
+            let size = meta.map(|meta| meta.len());
+            files.push(SourceFile { name, size });
+        };
+    }
+    Ok(())
+}
+
+fn folder_modified(folder: &Path) -> Option<SystemTime> {
+    metadata(folder).and_then(|meta| meta.modified()).ok()
+}
+
+/// The latest modification time of this folder and its subfolders
+fn latest_modification(folder: &Path) -> Option<SystemTime> {
+    let mut latest = folder_modified(folder)?;
+    if let Ok(entries) = read_dir(folder) {
+        for entry in entries.flatten() {
+            if matches!(entry.file_type(), Ok(file_type) if file_type.is_dir()) {
+                if let Some(modified) = latest_modification(&entry.path()) {
+                    latest = latest.max(modified);
+                };
+            };
+        }
+    };
+    Some(latest)
+}
+
+/// Pages stored in a zip archive. They are decompressed in memory when opened.
+#[derive(Debug)]
+pub struct ArchiveSource {
+    path: PathBuf,
+    /// The archive, opened on first use, with the modification time it had then
+    archive: Mutex<Option<(Option<SystemTime>, Archive)>>,
+}
+
+impl ArchiveSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            archive: Mutex::new(None),
+        }
+    }
+
+    /// Run `action` on the archive, opening it again if it was never opened or was modified
+    /// since it was opened
+    fn with_archive<T>(
+        &self,
+        action: impl FnOnce(&mut Archive) -> Result<T, ArchiveError>,
+    ) -> Result<T, PageSourceError> {
+        let mut archive = self.archive.lock().unwrap();
+        let modified = self.source_modified();
+        let outdated = match &*archive {
+            Some((opened_modified, _)) => modified.is_none() || *opened_modified != modified,
+            None => true,
+        };
+        if outdated {
+            *archive = Some((modified, Archive::open(&self.path)?));
+        };
+        // it was just opened if it wasn't already
+        let (_, archive) = archive.as_mut().unwrap();
+        Ok(action(archive)?)
+    }
+}
+
+impl PageSource for ArchiveSource {
+    fn location(&self) -> &Path {
+        &self.path
+    }
+
+    fn list_files(&self) -> Result<Vec<SourceFile>, PageSourceError> {
+        Ok(self
+            .with_archive(Archive::list_files)?
+            .into_iter()
+            .map(|(name, size)| SourceFile {
+                name,
+                size: Some(size),
+            })
+            .collect())
+    }
+
+    fn open(&self, name: &str) -> Result<PageReader, PageSourceError> {
+        let data = self.with_archive(|archive| archive.read_entry(name))?;
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn size(&self, name: &str) -> Result<u64, PageSourceError> {
+        self.with_archive(|archive| archive.entry_size(name))
+    }
+
+    /// The archive entries don't have a reliable modification time, so the one of the
+    /// archive is used instead
+    fn modified(&self, _name: &str) -> Option<SystemTime> {
+        self.source_modified()
+    }
+
+    fn source_modified(&self) -> Option<SystemTime> {
+        metadata(&self.path).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::io::Write;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn write_archive(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn read_page(source: &dyn PageSource, name: &str) -> String {
+        let mut content = String::new();
+        source
+            .open(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn folder_loops_are_listed_once() {
+        let folder = std::env::temp_dir().join(format!(
+            "marblecomic-folder-loop-{}",
+            std::process::id()
+        ));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(folder.join("chapter")).unwrap();
+        std::fs::write(folder.join("chapter/page.png"), "page").unwrap();
+        std::os::unix::fs::symlink(&folder, folder.join("chapter/loop")).unwrap();
+        std::os::unix::fs::symlink(folder.join("chapter"), folder.join("other")).unwrap();
+
+        let (files, folders) = DirectorySource::new(folder.clone())
+            .list_files_and_folders()
+            .unwrap();
+        let mut names: Vec<String> = files.into_iter().map(|file| file.name).collect();
+        names.sort();
+        // the link to a sibling folder is followed, but not the one to a parent folder
+        assert_eq!(names, vec!["chapter/page.png", "other/page.png"]);
+        let mut folders = folders;
+        folders.sort();
+        assert_eq!(folders, vec!["chapter", "other"]);
+
+        remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn archives_are_opened_again_once_modified() {
+        let folder = std::env::temp_dir().join(format!(
+            "marblecomic-archive-source-{}",
+            std::process::id()
+        ));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        let path = folder.join("comic.cbz");
+        write_archive(&path, &[("00000-00000.png", "first")]);
+
+        let source = ArchiveSource::new(path.clone());
+        assert_eq!(read_page(&source, "00000-00000.png"), "first");
+        assert_eq!(source.size("00000-00000.png").unwrap(), 5);
+        // the opened archive is reused while it isn't modified
+        assert_eq!(read_page(&source, "00000-00000.png"), "first");
+        assert!(source.open("00000-00001.png").is_err());
+
+        sleep(Duration::from_millis(50));
+        write_archive(
+            &path,
+            &[("00000-00000.png", "changed"), ("00000-00001.png", "added")],
+        );
+        assert_eq!(read_page(&source, "00000-00000.png"), "changed");
+        assert_eq!(read_page(&source, "00000-00001.png"), "added");
+        let names: Vec<String> = source
+            .list_files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(names, vec!["00000-00000.png", "00000-00001.png"]);
+
+        remove_dir_all(&folder).unwrap();
+    }
+}
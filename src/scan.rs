@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::read_dir;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::navigation_cache::{list_source_files, source_modified};
+use crate::{
+    Comic, ComicDatabase, ComicDatabaseLoadError, LoadProblem, NavigationCache, PageSource,
+    PageSourceError, SharedComicDatabase, SourceFile,
+};
+
+/// How far the loading of the library with [`scan_library`] is. It can be read while the
+/// library is loading.
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    total: AtomicUsize,
+    done: AtomicUsize,
+    finished: AtomicBool,
+    /// The message of the error the scan stopped on, with its causes
+    error: Mutex<Option<String>>,
+}
+
+impl ScanProgress {
+    /// The number of comic folders and archives to read. It is 0 until all the library roots
+    /// are listed.
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    /// The number of comic folders and archives that are read and added to the database
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    /// True once the scan is over, even if it stopped on an error
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// The error the scan stopped on, if any. The comics that weren't read yet are missing from
+    /// the database.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+/// A comic that was read, but not yet added to the database
+pub(crate) struct ScannedContent {
+    pub comic: Comic,
+    pub pages: Arc<dyn PageSource>,
+    pub modified: Option<SystemTime>,
+    /// The files of the page source, or `None` if the comic isn't found (in which case they
+    /// aren't listed)
+    pub files: Option<Result<Vec<SourceFile>, PageSourceError>>,
+}
+
+/// The result of reading a subfolder (or an archive) of a library root
+pub(crate) struct ScannedComic {
+    pub path: PathBuf,
+    /// The index of the library root
+    pub root: usize,
+    /// `None` if there is no comic at this path
+    pub content: Result<Option<ScannedContent>, ComicDatabaseLoadError>,
+}
+
+fn scan_location(path: PathBuf, root: usize, cache: Option<&NavigationCache>) -> ScannedComic {
+    let content = ComicDatabase::read_comic_location(&path).map(|location| {
+        location.map(|(comic, pages)| {
+            let modified = source_modified(cache, &*pages);
+            let files = if comic.found {
+                Some(list_source_files(cache, &*pages, modified))
+            } else {
+                None
+            };
+            ScannedContent {
+                comic,
+                pages,
+                modified,
+                files,
+            }
+        })
+    });
+    ScannedComic {
+        path,
+        root,
+        content,
+    }
+}
+
+/// Load all the comics of these library roots in `comic_database`, reading them on
+/// `thread_count` threads.
+///
+/// Each comic is added as soon as it is read, with its navigation already computed, so the
+/// database can be used while the library is loading. The comics are still added in the order
+/// [`ComicDatabase::load_from_dirs`] would add them, so the duplicate ids are handled the same
+/// way. The database is only locked for writing while adding a comic.
+///
+/// If `lenient` is true, the errors are recorded and the comics that can't be loaded are
+/// skipped, like with [`ComicDatabase::load_from_dirs_lenient`]. Otherwise, the scan stop at
+/// the first error, which is also kept in `progress`.
+pub fn scan_library(
+    comic_database: &SharedComicDatabase,
+    roots: &[PathBuf],
+    lenient: bool,
+    thread_count: usize,
+    progress: &ScanProgress,
+) -> Result<(), ComicDatabaseLoadError> {
+    let result = scan_roots(comic_database, roots, lenient, thread_count, progress);
+    if let Err(err) = &result {
+        let mut message = err.to_string();
+        let mut source = err.source();
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        *progress.error.lock().unwrap() = Some(message);
+    };
+    progress.finished.store(true, Ordering::SeqCst);
+    result
+}
+
+fn scan_roots(
+    comic_database: &SharedComicDatabase,
+    roots: &[PathBuf],
+    lenient: bool,
+    thread_count: usize,
+    progress: &ScanProgress,
+) -> Result<(), ComicDatabaseLoadError> {
+    let on_error = |err| {
+        if lenient {
+            let mut comic_database = comic_database.write().unwrap();
+            comic_database.record_load_problem(LoadProblem::Load(err));
+            Ok(())
+        } else {
+            Err(err)
+        }
+    };
+
+    let mut locations = Vec::new();
+    for folder in roots {
+        let root = comic_database
+            .write()
+            .unwrap()
+            .register_root(folder.clone());
+        let paths = match read_dir(folder) {
+            Ok(paths) => paths,
+            Err(err) => {
+                on_error(ComicDatabaseLoadError::CantReadDirectory(
+                    err,
+                    folder.clone(),
+                ))?;
+                continue;
+            }
+        };
+        for path in paths {
+            match path {
+                Ok(entry) => locations.push((entry.path(), root)),
+                Err(err) => on_error(ComicDatabaseLoadError::CantReadDirEntry(
+                    err,
+                    folder.clone(),
+                ))?,
+            };
+        }
+    }
+    progress.total.store(locations.len(), Ordering::SeqCst);
+
+    let cache = comic_database.read().unwrap().listing_cache();
+    let queue = Arc::new(Mutex::new(locations.into_iter().enumerate()));
+    let (sender, receiver) = channel();
+    for _ in 0..thread_count.max(1) {
+        let queue = queue.clone();
+        let sender = sender.clone();
+        let cache = cache.clone();
+        thread::spawn(move || loop {
+            let next = queue.lock().unwrap().next();
+            let (index, (path, root)) = match next {
+                Some(location) => location,
+                None => break,
+            };
+            let scanned = scan_location(path, root, cache.as_deref());
+            // the receiver is dropped if the scan stopped on an error
+            if sender.send((index, scanned)).is_err() {
+                break;
+            };
+        });
+    }
+    drop(sender);
+
+    // the comics are read out of order, so they wait here until all the ones before them
+    // are added
+    let mut pending = BTreeMap::new();
+    let mut next_index = 0;
+    for (index, scanned) in receiver {
+        pending.insert(index, scanned);
+        while let Some(scanned) = pending.remove(&next_index) {
+            comic_database
+                .write()
+                .unwrap()
+                .add_scanned_comic(scanned, lenient)?;
+            next_index += 1;
+            progress.done.store(next_index, Ordering::SeqCst);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::Path;
+    use std::sync::RwLock;
+
+    use crate::DuplicateIdPolicy;
+
+    /// A library of 30 comics, in a folder unique to this test. The first 20 share 10 ids, and
+    /// the others have no id.
+    fn test_library(name: &str) -> PathBuf {
+        let library =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&library);
+        for index in 0..30 {
+            let folder = library.join(format!("c{:02}", index));
+            create_dir_all(&folder).unwrap();
+            let data = if index < 20 {
+                format!(r#"{{"id": {}, "keywords": {{}}, "translations": []}}"#, index % 10)
+            } else {
+                format!(r#"{{"slug": "c{}", "keywords": {{}}, "translations": []}}"#, index)
+            };
+            write(folder.join("data.json"), data).unwrap();
+            write(folder.join("00000-00000.png"), "page").unwrap();
+        }
+        // the comic listed first is the slowest to read, so it is added after the others are
+        // read
+        let first = read_dir(&library).unwrap().next().unwrap().unwrap().path();
+        for page in 1..2000 {
+            write(first.join(format!("00000-{:05}.png", page)), "page").unwrap();
+        }
+        library
+    }
+
+    fn new_shared_database(policy: DuplicateIdPolicy) -> SharedComicDatabase {
+        Arc::new(RwLock::new(ComicDatabase::new(policy)))
+    }
+
+    fn comic_paths(comic_database: &ComicDatabase) -> Vec<(usize, PathBuf)> {
+        comic_database
+            .comics()
+            .iter()
+            .map(|(comic_id, entry)| (comic_id, entry.path.clone()))
+            .collect()
+    }
+
+    fn duplicate_paths(comic_database: &ComicDatabase) -> Vec<(PathBuf, PathBuf)> {
+        comic_database
+            .duplicate_ids()
+            .iter()
+            .map(|duplicate| (duplicate.first_path.clone(), duplicate.second_path.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn comics_are_added_in_the_listing_order() {
+        let library = test_library("scan-order");
+        let roots = [library.clone()];
+        let mut loaded = ComicDatabase::new(DuplicateIdPolicy::KeepFirst);
+        loaded.load_from_dirs(&roots).unwrap();
+
+        let scanned = new_shared_database(DuplicateIdPolicy::KeepFirst);
+        let progress = ScanProgress::default();
+        scan_library(&scanned, &roots, false, 8, &progress).unwrap();
+        let scanned = scanned.read().unwrap();
+        assert_eq!(scanned.comics().len(), 20);
+        // the same comics are kept, and the comics without id are given the same ids
+        assert_eq!(comic_paths(&scanned), comic_paths(&loaded));
+        assert_eq!(duplicate_paths(&scanned), duplicate_paths(&loaded));
+        assert_eq!(scanned.duplicate_ids().len(), 10);
+
+        assert!(progress.is_finished());
+        assert_eq!(progress.total(), 30);
+        assert_eq!(progress.done(), 30);
+        assert_eq!(progress.error(), None);
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    fn break_comic(library: &Path) -> PathBuf {
+        let broken = library.join("c25");
+        write(broken.join("data.json"), "{ not json").unwrap();
+        broken
+    }
+
+    #[test]
+    fn strict_scans_stop_at_the_first_error() {
+        let library = test_library("scan-strict");
+        break_comic(&library);
+        let comic_database = new_shared_database(DuplicateIdPolicy::KeepFirst);
+        let progress = ScanProgress::default();
+        let result = scan_library(&comic_database, &[library.clone()], false, 4, &progress);
+        assert!(matches!(
+            result,
+            Err(ComicDatabaseLoadError::CantDeserializeComic(_, _))
+        ));
+
+        assert!(progress.is_finished());
+        assert_eq!(progress.total(), 30);
+        // the comics after the broken one aren't added
+        assert!(progress.done() < 30);
+        assert_eq!(
+            comic_database.read().unwrap().comics().len()
+                + comic_database.read().unwrap().duplicate_ids().len(),
+            progress.done()
+        );
+        let error = progress.error().unwrap();
+        assert!(error.starts_with("failed to deserialize a comic data file"));
+        assert!(error.contains("c25"));
+
+        remove_dir_all(&library).unwrap();
+    }
+
+    #[test]
+    fn lenient_scans_skip_the_broken_comics() {
+        let library = test_library("scan-lenient");
+        let broken = break_comic(&library);
+        let comic_database = new_shared_database(DuplicateIdPolicy::KeepFirst);
+        let progress = ScanProgress::default();
+        scan_library(&comic_database, &[library.clone()], true, 4, &progress).unwrap();
+
+        assert!(progress.is_finished());
+        assert_eq!(progress.total(), 30);
+        assert_eq!(progress.done(), 30);
+        assert_eq!(progress.error(), None);
+        let comic_database = comic_database.read().unwrap();
+        assert_eq!(comic_database.comics().len(), 19);
+        match comic_database.load_problems() {
+            [LoadProblem::Load(ComicDatabaseLoadError::CantDeserializeComic(_, path))] => {
+                assert_eq!(path.parent(), Some(&*broken))
+            }
+            problems => panic!("unexpected problems: {:?}", problems),
+        };
+
+        remove_dir_all(&library).unwrap();
+    }
+}
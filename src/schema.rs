@@ -0,0 +1,271 @@
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use std::fs::{read_dir, rename, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Comic;
+
+/// The version of the data.json layout read and written by this version of marblecomic. The
+/// files without a `schema_version` are of the version 0.
+pub const CURRENT_SCHEMA_VERSION: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("the data isn't valid json")]
+    InvalidJson(#[source] serde_json::Error),
+    #[error("the data isn't a json object")]
+    NotAnObject,
+    #[error("the schema_version {0} isn't a positive integer")]
+    InvalidVersion(Value),
+    #[error("the schema version {0} is newer than the version {} supported by this version of marblecomic", CURRENT_SCHEMA_VERSION)]
+    UnsupportedVersion(u64),
+    #[error("can't upgrade the data from the schema version {0} : {1}")]
+    MigrationFailed(u64, String),
+    #[error("the data doesn't match the comic schema")]
+    InvalidComic(#[source] serde_json::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum UpgradeFileError {
+    #[error("failed to list sub content of {1}")]
+    CantReadDirectory(#[source] io::Error, PathBuf),
+    #[error("failed to open file at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("the data file at {1} can't be upgraded")]
+    Schema(#[source] SchemaError, PathBuf),
+    #[error("failed to write the data file at {1}")]
+    CantWriteFile(#[source] io::Error, PathBuf),
+}
+
+/// Upgrade the data of a version `n` to the version `n + 1`
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// The migration at the index `n` upgrade from the version `n`
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] = [migrate_from_v0];
+
+/// The version 1 only made `found` optional (defaulting to true), so the data of the version 0
+/// is already valid.
+fn migrate_from_v0(_data: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// The schema version of this data.json content
+pub fn schema_version(data: &Map<String, Value>) -> Result<u64, SchemaError> {
+    match data.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| SchemaError::InvalidVersion(version.clone())),
+        None => Ok(0),
+    }
+}
+
+/// Upgrade this data.json content to the [`CURRENT_SCHEMA_VERSION`]. The fields the migrations
+/// don't know about are left untouched. Return the version it had.
+pub fn migrate_comic_data(data: &mut Value) -> Result<u64, SchemaError> {
+    migrate_with(data, &MIGRATIONS)
+}
+
+/// Upgrade this data.json content with these migrations, the last one upgrading to the version
+/// `migrations.len()`. Return the version it had.
+fn migrate_with(data: &mut Value, migrations: &[Migration]) -> Result<u64, SchemaError> {
+    let data = data.as_object_mut().ok_or(SchemaError::NotAnObject)?;
+    let version = schema_version(data)?;
+    let latest_version = migrations.len() as u64;
+    if version > latest_version {
+        return Err(SchemaError::UnsupportedVersion(version));
+    };
+    for (from, migration) in migrations.iter().enumerate().skip(version as usize) {
+        migration(data).map_err(|err| SchemaError::MigrationFailed(from as u64, err))?;
+    }
+    data.insert("schema_version".into(), latest_version.into());
+    Ok(version)
+}
+
+/// Read a comic from a data.json of any supported schema version
+pub fn read_comic_data<R: Read>(reader: R) -> Result<Comic, SchemaError> {
+    let mut data: Value = serde_json::from_reader(reader).map_err(SchemaError::InvalidJson)?;
+    migrate_comic_data(&mut data)?;
+    serde_json::from_value(data).map_err(SchemaError::InvalidComic)
+}
+
+/// The data.json content of this comic, in the current schema version
+pub fn comic_to_data(comic: &Comic) -> Result<Value, serde_json::Error> {
+    let mut data = serde_json::to_value(comic)?;
+    if let Some(fields) = data.as_object_mut() {
+        fields.insert("schema_version".into(), CURRENT_SCHEMA_VERSION.into());
+    };
+    Ok(data)
+}
+
+/// Rewrite this data.json content in the current schema version, as it would be written for the
+/// comic it contain. The fields that aren't part of the schema are kept as is.
+pub fn upgrade_comic_data(mut data: Value) -> Result<Value, SchemaError> {
+    migrate_comic_data(&mut data)?;
+    let comic: Comic = serde_json::from_value(data.clone()).map_err(SchemaError::InvalidComic)?;
+    let mut upgraded = comic_to_data(&comic).map_err(SchemaError::InvalidComic)?;
+    if let (Some(fields), Value::Object(original_fields)) = (upgraded.as_object_mut(), data) {
+        for (key, value) in original_fields {
+            fields.entry(key).or_insert(value);
+        }
+    };
+    Ok(upgraded)
+}
+
+/// Upgrade the data.json at `path` to the current schema version, unless it already is. Return
+/// the version it had. If `dry_run` is true, the file is only checked.
+///
+/// The new content is written to a temporary file first, so the data.json is either the old or
+/// the new one if the upgrade is interrupted.
+pub fn upgrade_data_file(path: &Path, dry_run: bool) -> Result<u64, UpgradeFileError> {
+    let data_file =
+        File::open(path).map_err(|err| UpgradeFileError::CantOpenFile(err, path.to_path_buf()))?;
+    let mut data: Value = serde_json::from_reader(data_file)
+        .map_err(|err| UpgradeFileError::Schema(SchemaError::InvalidJson(err), path.into()))?;
+    let version = data
+        .as_object()
+        .ok_or(SchemaError::NotAnObject)
+        .and_then(schema_version)
+        .map_err(|err| UpgradeFileError::Schema(err, path.into()))?;
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok(version);
+    };
+    data = upgrade_comic_data(data).map_err(|err| UpgradeFileError::Schema(err, path.into()))?;
+    if dry_run {
+        return Ok(version);
+    };
+
+    let mut temporary_path = path.as_os_str().to_os_string();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+    let write_temporary_file = || -> io::Result<()> {
+        let mut temporary_file = File::create(&temporary_path)?;
+        serde_json::to_writer_pretty(&mut temporary_file, &data)?;
+        temporary_file.write_all(b"\n")?;
+        temporary_file.sync_all()
+    };
+    write_temporary_file()
+        .map_err(|err| UpgradeFileError::CantWriteFile(err, temporary_path.clone()))?;
+    rename(&temporary_path, path)
+        .map_err(|err| UpgradeFileError::CantWriteFile(err, path.to_path_buf()))?;
+    Ok(version)
+}
+
+/// The data.json files of the comic folders in these library roots. The ones inside an archive
+/// aren't listed, as they can't be rewritten.
+pub fn list_data_files(roots: &[PathBuf]) -> Result<Vec<PathBuf>, UpgradeFileError> {
+    let mut data_files = Vec::new();
+    for root in roots {
+        let entries =
+            read_dir(root).map_err(|err| UpgradeFileError::CantReadDirectory(err, root.clone()))?;
+        for entry in entries {
+            let data_path = entry
+                .map_err(|err| UpgradeFileError::CantReadDirectory(err, root.clone()))?
+                .path()
+                .join("data.json");
+            if data_path.is_file() {
+                data_files.push(data_path);
+            };
+        }
+    }
+    data_files.sort();
+    Ok(data_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn version_0_is_upgraded() {
+        let mut data = json!({
+            "id": 1,
+            "comic_name": "name",
+            "description": null,
+            "keywords": {},
+            "translations": [],
+        });
+        assert_eq!(migrate_comic_data(&mut data).unwrap(), 0);
+        assert_eq!(data["schema_version"], json!(CURRENT_SCHEMA_VERSION));
+
+        // found is optional since the version 1
+        let comic = read_comic_data(data.to_string().as_bytes()).unwrap();
+        assert_eq!(comic.declared_id, Some(1));
+        assert!(comic.found);
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let mut data = json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1 });
+        assert!(matches!(
+            migrate_comic_data(&mut data),
+            Err(SchemaError::UnsupportedVersion(version)) if version == CURRENT_SCHEMA_VERSION + 1
+        ));
+        let mut data = json!({ "schema_version": "1" });
+        assert!(matches!(
+            migrate_comic_data(&mut data),
+            Err(SchemaError::InvalidVersion(_))
+        ));
+        assert!(matches!(
+            migrate_comic_data(&mut json!([])),
+            Err(SchemaError::NotAnObject)
+        ));
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let data = json!({
+            "id": 1,
+            "comic_name": null,
+            "description": null,
+            "keywords": {"author": ["someone"]},
+            "translations": [],
+            "found": true,
+            "custom": {"source": "scanned"},
+        });
+        let upgraded = upgrade_comic_data(data).unwrap();
+        assert_eq!(upgraded["custom"], json!({"source": "scanned"}));
+        assert_eq!(upgraded["keywords"], json!({"author": ["someone"]}));
+        assert_eq!(upgraded["schema_version"], json!(CURRENT_SCHEMA_VERSION));
+    }
+
+    fn rename_title(data: &mut Map<String, Value>) -> Result<(), String> {
+        let title = data.remove("title").ok_or("no title")?;
+        data.insert("comic_name".into(), title);
+        Ok(())
+    }
+
+    fn split_tags(data: &mut Map<String, Value>) -> Result<(), String> {
+        let tags = data.remove("tags").unwrap_or_else(|| json!([]));
+        data.insert("keywords".into(), json!({ "tag": tags }));
+        Ok(())
+    }
+
+    #[test]
+    fn migrations_are_applied_in_order_from_the_version_of_the_data() {
+        let migrations: [Migration; 2] = [rename_title, split_tags];
+
+        let mut data = json!({ "title": "name", "tags": ["a"] });
+        assert_eq!(migrate_with(&mut data, &migrations).unwrap(), 0);
+        assert_eq!(
+            data,
+            json!({ "comic_name": "name", "keywords": {"tag": ["a"]}, "schema_version": 2 })
+        );
+
+        // the data of the version 1 has no title to rename
+        let mut data = json!({ "schema_version": 1, "comic_name": "name", "tags": ["a"] });
+        assert_eq!(migrate_with(&mut data, &migrations).unwrap(), 1);
+        assert_eq!(
+            data,
+            json!({ "comic_name": "name", "keywords": {"tag": ["a"]}, "schema_version": 2 })
+        );
+
+        let mut data = json!({ "tags": ["a"] });
+        assert!(matches!(
+            migrate_with(&mut data, &migrations),
+            Err(SchemaError::MigrationFailed(0, _))
+        ));
+    }
+}
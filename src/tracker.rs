@@ -1,11 +1,15 @@
-use std::collections::HashMap;
-use std::fs::File;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
 use std::io;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::Comic;
+use crate::{Comic, ComicDatabase, ComicRef};
 
 use thiserror::Error;
 
@@ -13,6 +17,10 @@ use thiserror::Error;
 pub enum TrackerReadError {
     #[error("can't decode a json value while trying to load reading progress")]
     DecodeError(#[from] serde_json::Error),
+    #[error("can't open the progress file {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't decode the progress file {1}, and none of its backups")]
+    CantDecodeFile(#[source] serde_json::Error, PathBuf),
 }
 
 #[derive(Error, Debug)]
@@ -21,55 +29,834 @@ pub enum TrackerSaveError {
     CantCreateFile(#[source] io::Error, PathBuf),
     #[error("can't serialize the data structure")]
     SerializeError(#[from] serde_json::Error),
-    #[error("can't write to the file {0}")]
+    #[error("can't write to the file {1}")]
     CantWriteFile(#[source] io::Error, PathBuf),
+    #[error("can't replace the file {1} with the new progress")]
+    CantReplaceFile(#[source] io::Error, PathBuf),
+    #[error("can't back up the file {1}")]
+    CantBackUpFile(#[source] io::Error, PathBuf),
+    #[error("can't remove the old backup {1}")]
+    CantRemoveBackup(#[source] io::Error, PathBuf),
+    #[error("can't write to the history file {1}")]
+    CantWriteHistory(#[source] io::Error, PathBuf),
+}
+
+/// A change of the progress of a comic
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    /// The text form of the [`Comic::reference`] of the comic
+    pub comic: String,
+    pub chapter: usize,
+    pub image: usize,
+    /// When the progress was set, in seconds since the unix epoch
+    pub time: u64,
+}
+
+/// The number of history entries kept in memory. The older ones are only in the history file,
+/// and in the export.
+pub const KEPT_HISTORY_ENTRIES: usize = 1000;
+
+#[derive(Default)]
+struct History {
+    /// The newest entries, from the oldest to the newest
+    entries: VecDeque<HistoryEntry>,
+    /// The number of entries at the end of `entries` not yet written to the history file. They
+    /// are kept even past [`KEPT_HISTORY_ENTRIES`].
+    unsaved: usize,
+    /// The time each comic was last read, including the comics whose entries are no longer in
+    /// memory
+    last_read: HashMap<String, u64>,
+    /// The history file the saved entries are in
+    path: Option<PathBuf>,
+    /// True if the history file end with a truncated line, that should be terminated before
+    /// adding new entries
+    missing_newline: bool,
+}
+
+impl History {
+    fn push(&mut self, entry: HistoryEntry) {
+        self.last_read.insert(entry.comic.clone(), entry.time);
+        self.entries.push_back(entry);
+        self.unsaved += 1;
+        self.forget_oldest();
+    }
+
+    /// Drop the oldest saved entries past [`KEPT_HISTORY_ENTRIES`]
+    fn forget_oldest(&mut self) {
+        while self.entries.len() > KEPT_HISTORY_ENTRIES && self.entries.len() > self.unsaved {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Where the reader is with a comic, set explicitly or when its progress change (see
+/// [`Tracker::set_progress`])
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingStatus {
+    PlanToRead,
+    Reading,
+    OnHold,
+    Dropped,
+    Completed,
+    #[serde(rename = "re-reading")]
+    Rereading,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 6] = [
+        Self::PlanToRead,
+        Self::Reading,
+        Self::OnHold,
+        Self::Dropped,
+        Self::Completed,
+        Self::Rereading,
+    ];
+
+    /// The name used in the progress file and in the urls, like `on-hold`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PlanToRead => "plan-to-read",
+            Self::Reading => "reading",
+            Self::OnHold => "on-hold",
+            Self::Dropped => "dropped",
+            Self::Completed => "completed",
+            Self::Rereading => "re-reading",
+        }
+    }
+
+    /// The name displayed to the reader, like `on hold`
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PlanToRead => "plan to read",
+            Self::Reading => "reading",
+            Self::OnHold => "on hold",
+            Self::Dropped => "dropped",
+            Self::Completed => "completed",
+            Self::Rereading => "re-reading",
+        }
+    }
+}
+
+impl FromStr for ReadingStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|status| status.name() == s)
+            .ok_or_else(|| format!("unknown reading status {:?}", s))
+    }
+}
+
+/// The highest rating a comic can be given. The ratings start at 1.
+pub const MAX_RATING: u8 = 10;
+
+/// What the reader noted about a comic
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorite: bool,
+    /// From 1 to [`MAX_RATING`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Annotation {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The content of a progress file
+#[derive(Serialize, Deserialize, Default)]
+struct TrackerFile {
+    progress: HashMap<String, (usize, usize)>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    statuses: HashMap<String, ReadingStatus>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    annotations: HashMap<String, Annotation>,
+}
+
+/// Everything a [`Tracker`] know about the reader, as exported by [`Tracker::export`]
+#[derive(Serialize)]
+struct TrackerExport<'a> {
+    #[serde(flatten)]
+    file: &'a TrackerFile,
+    history: Vec<HistoryEntry>,
+}
+
+/// The progress files written before the reading statuses only contain the progress
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTrackerFile {
+    Current(TrackerFile),
+    Progress(HashMap<String, (usize, usize)>),
+}
+
+fn decode_tracker_file<R: Read>(reader: R) -> Result<TrackerFile, serde_json::Error> {
+    Ok(match serde_json::from_reader(reader)? {
+        StoredTrackerFile::Current(file) => file,
+        StoredTrackerFile::Progress(progress) => TrackerFile {
+            progress,
+            ..TrackerFile::default()
+        },
+    })
 }
 
 #[derive(Default)]
 pub struct Tracker {
-    pub data: Mutex<HashMap<usize, (usize, usize)>>, //TODO: use dashmap
+    /// The progress of the comics, by the text form of their [`Comic::reference`]
+    pub data: Mutex<HashMap<String, (usize, usize)>>, //TODO: use dashmap
+    /// The reading status of the comics, by the text form of their [`Comic::reference`]
+    statuses: Mutex<HashMap<String, ReadingStatus>>,
+    /// The favorites, ratings and notes of the comics, by the text form of their
+    /// [`Comic::reference`]
+    annotations: Mutex<HashMap<String, Annotation>>,
+    history: Mutex<History>,
+    /// The backup this tracker was read from, if its file couldn't be decoded. It is only
+    /// reported once.
+    recovered_from: Mutex<Option<PathBuf>>,
+    /// Held during a save, so the saves don't share the temporary file, and an older snapshot
+    /// can't replace a newer one
+    saving: Mutex<()>,
+}
+
+/// The file the history of the tracker saved at `path` is appended to, like
+/// `progress.history.jsonl` for `progress.json`. It contain one json [`HistoryEntry`] per line.
+pub fn history_path_of(path: &Path) -> PathBuf {
+    path.with_extension("history.jsonl")
 }
 
 impl Tracker {
     pub fn new_from_reader<R: Read>(reader: R) -> Result<Self, TrackerReadError> {
-        let data = Mutex::new(serde_json::from_reader(reader)?);
-        Ok(Self { data })
+        let file = decode_tracker_file(reader)?;
+        Ok(Self::from_file(file, History::default(), None))
+    }
+
+    fn from_file(file: TrackerFile, history: History, recovered_from: Option<PathBuf>) -> Self {
+        Self {
+            data: Mutex::new(file.progress),
+            statuses: Mutex::new(file.statuses),
+            annotations: Mutex::new(file.annotations),
+            history: Mutex::new(history),
+            recovered_from: Mutex::new(recovered_from),
+            saving: Mutex::new(()),
+        }
+    }
+
+    /// Read the progress saved at `path`, starting with no progress if there is no such file.
+    ///
+    /// If the file can't be decoded (like if it was damaged by a crash), the progress is read
+    /// from the newest backup that can be decoded instead (see [`Tracker::save`]).
+    ///
+    /// The history is read from the file given by [`history_path_of`].
+    pub fn load(path: &Path) -> Result<Self, TrackerReadError> {
+        let history = load_history(&history_path_of(path))?;
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::from_file(TrackerFile::default(), history, None))
+            }
+            Err(err) => return Err(TrackerReadError::CantOpenFile(err, path.to_path_buf())),
+        };
+        let decode_error = match decode_tracker_file(io::BufReader::new(file)) {
+            Ok(file) => return Ok(Self::from_file(file, history, None)),
+            Err(err) => err,
+        };
+        for backup in list_backups(path).into_iter().rev() {
+            if let Ok(file) = File::open(&backup) {
+                if let Ok(file) = decode_tracker_file(io::BufReader::new(file)) {
+                    return Ok(Self::from_file(file, history, Some(backup)));
+                };
+            };
+        }
+        Err(TrackerReadError::CantDecodeFile(
+            decode_error,
+            path.to_path_buf(),
+        ))
     }
 
-    pub fn get_progress(&self, comic: &Comic) -> (usize, usize) {
+    /// The backup this tracker was recovered from by [`Tracker::load`]. It is only returned by
+    /// the first call.
+    pub fn take_recovered_from(&self) -> Option<PathBuf> {
+        self.recovered_from.lock().unwrap().take()
+    }
+
+    /// The progress of this comic, or of one of its translations
+    pub fn get_progress(&self, comic: &Comic, comic_database: &ComicDatabase) -> (usize, usize) {
         let data = self.data
             .lock()
             .unwrap();
-        if let Some(progress) = data.get(&comic.id) {
-            return progress.clone()
+        if let Some(progress) = data.get(&comic.reference().to_string()) {
+            return *progress
         };
-        for translated_id in comic.translations.iter().map(|(_, id)| id) {
-            if let Some(progress) = data.get(&translated_id) {
-                return progress.clone();
+        for translation in comic.translations.iter().map(|(_, reference)| reference) {
+            // the translation may be referenced by id while its progress is saved by slug
+            let reference = match comic_database.get_comic_by_ref(translation) {
+                Some(translated_comic) => translated_comic.reference(),
+                None => translation.clone(),
+            };
+            if let Some(progress) = data.get(&reference.to_string()) {
+                return *progress;
             }
         }
         (0, 0)
     }
 
-    pub fn set_progress(&self, comic_id: usize, chapter_id: usize, image_id: usize) {
+    /// Set the progress of a comic, and add it to the history. `finished` tell if this is the
+    /// last page of the comic.
+    ///
+    /// The reading status is updated to follow the progress: the comic is completed once its
+    /// last page is reached, and is read again if an earlier page is set after that. A comic on
+    /// hold or dropped keep its status until it is finished.
+    pub fn set_progress(
+        &self,
+        comic: &ComicRef,
+        chapter_id: usize,
+        image_id: usize,
+        finished: bool,
+    ) {
         self.data
             .lock()
             .unwrap()
-            .insert(comic_id, (chapter_id, image_id));
+            .insert(comic.to_string(), (chapter_id, image_id));
+        {
+            let mut statuses = self.statuses.lock().unwrap();
+            let status = statuses.get(&comic.to_string()).copied();
+            let new_status = match (status, finished) {
+                (_, true) => ReadingStatus::Completed,
+                (None, false) | (Some(ReadingStatus::PlanToRead), false) => ReadingStatus::Reading,
+                (Some(ReadingStatus::Completed), false) => ReadingStatus::Rereading,
+                (Some(status), false) => status,
+            };
+            statuses.insert(comic.to_string(), new_status);
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.history.lock().unwrap().push(HistoryEntry {
+            comic: comic.to_string(),
+            chapter: chapter_id,
+            image: image_id,
+            time,
+        });
+    }
+
+    /// The comics with a saved progress, the most recently read first. The ones that aren't in
+    /// the history (their progress was saved before it existed) come last.
+    pub fn list_comic_with_progress(&self) -> Vec<ComicRef> {
+        let last_read = self.last_read_times();
+        let mut comics: Vec<(Option<u64>, String)> = self
+            .data
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|k| (last_read.get(k).copied(), k.clone()))
+            .collect();
+        comics.sort_by(|(time_1, comic_1), (time_2, comic_2)| {
+            time_2.cmp(time_1).then_with(|| comic_1.cmp(comic_2))
+        });
+        comics
+            .into_iter()
+            .map(|(_, k)| ComicRef::from(k.as_str()))
+            .collect()
+    }
+
+    /// The reading status of this comic, if it has one
+    pub fn get_status(&self, comic: &ComicRef) -> Option<ReadingStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(&comic.to_string())
+            .copied()
+    }
+
+    /// Set the reading status of a comic, or remove it with `None`
+    pub fn set_status(&self, comic: &ComicRef, status: Option<ReadingStatus>) {
+        let mut statuses = self.statuses.lock().unwrap();
+        match status {
+            Some(status) => statuses.insert(comic.to_string(), status),
+            None => statuses.remove(&comic.to_string()),
+        };
+    }
+
+    /// The favorite, rating and note of this comic. They are all unset if it has none.
+    pub fn get_annotation(&self, comic: &ComicRef) -> Annotation {
+        self.annotations
+            .lock()
+            .unwrap()
+            .get(&comic.to_string())
+            .cloned()
+            .unwrap_or_default()
     }
 
-    pub fn list_comic_with_progress(&self) -> Vec<usize> {
-        self.data.lock().unwrap().iter().map(|(k, _)| *k).collect()
+    /// Set the favorite, rating and note of a comic. The rating should be between 1 and
+    /// [`MAX_RATING`].
+    pub fn set_annotation(&self, comic: &ComicRef, annotation: Annotation) {
+        let mut annotations = self.annotations.lock().unwrap();
+        if annotation.is_empty() {
+            annotations.remove(&comic.to_string());
+        } else {
+            annotations.insert(comic.to_string(), annotation);
+        };
+    }
+
+    /// The time each comic was last read, by the text form of its [`Comic::reference`]
+    pub fn last_read_times(&self) -> HashMap<String, u64> {
+        self.history.lock().unwrap().last_read.clone()
+    }
+
+    /// `count` progress changes, the newest first, after skipping the `skip` newest ones. Only
+    /// the last [`KEPT_HISTORY_ENTRIES`] changes are available, the older ones are only
+    /// exported (see [`Tracker::export`]).
+    pub fn history(&self, skip: usize, count: usize) -> Vec<HistoryEntry> {
+        let history = self.history.lock().unwrap();
+        history
+            .entries
+            .iter()
+            .rev()
+            .skip(skip)
+            .take(count)
+            .cloned()
+            .collect()
     }
 
-    pub fn save(&self, path: &PathBuf) -> Result<(), TrackerSaveError> {
+    /// Save the progress to `path`, keeping the `backup_count` previous versions of the file.
+    ///
+    /// The progress is written to a temporary file, which replace the previous one once it is
+    /// completely written, so a crash or a full disk can't leave a truncated file. The previous
+    /// version is kept as `<file name>.<timestamp>.bak` next to it, and the oldest backups are
+    /// removed.
+    ///
+    /// The new history entries are then appended to the history file.
+    pub fn save(&self, path: &PathBuf, backup_count: usize) -> Result<(), TrackerSaveError> {
+        let _saving = self.saving.lock().unwrap();
         //do not use a serde_json::to_writer, as an error in this case will result to the tracker file being empty
-        let value_vec = serde_json::to_vec_pretty(&*self.data.lock().unwrap())?;
-        let mut writer = File::create(path)
-            .map_err(|err| TrackerSaveError::CantCreateFile(err, path.clone()))?;
+        let value_vec = serde_json::to_vec_pretty(&self.to_file())?;
+
+        let mut temporary_path = path.as_os_str().to_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let mut writer = File::create(&temporary_path)
+            .map_err(|err| TrackerSaveError::CantCreateFile(err, temporary_path.clone()))?;
         writer
             .write_all(&value_vec)
-            .map_err(|err| TrackerSaveError::CantWriteFile(err, path.clone()))?;
+            .and_then(|_| writer.sync_all())
+            .map_err(|err| TrackerSaveError::CantWriteFile(err, temporary_path.clone()))?;
+        drop(writer);
+
+        if backup_count > 0 && path.exists() {
+            back_up(path)?;
+        };
+        fs::rename(&temporary_path, path)
+            .map_err(|err| TrackerSaveError::CantReplaceFile(err, path.clone()))?;
+        // make the rename itself durable. Directories can't be opened on every platform, so
+        // this is only done when possible.
+        if let Some(parent) = path.parent() {
+            let parent = if parent == Path::new("") {
+                Path::new(".")
+            } else {
+                parent
+            };
+            if let Ok(directory) = File::open(parent) {
+                let _ = directory.sync_all();
+            };
+        };
+
+        let backups = list_backups(path);
+        if backups.len() > backup_count {
+            for backup in &backups[..backups.len() - backup_count] {
+                fs::remove_file(backup)
+                    .map_err(|err| TrackerSaveError::CantRemoveBackup(err, backup.clone()))?;
+            }
+        };
+
+        self.append_history(&history_path_of(path))
+    }
+
+    fn to_file(&self) -> TrackerFile {
+        TrackerFile {
+            progress: self.data.lock().unwrap().clone(),
+            statuses: self.statuses.lock().unwrap().clone(),
+            annotations: self.annotations.lock().unwrap().clone(),
+        }
+    }
+
+    /// All the data of the reader as json: the content of the progress file (the `progress`,
+    /// `statuses` and `annotations`), and the `history`, from the oldest to the newest. The
+    /// whole history is exported, so the saved entries are read back from the history file.
+    pub fn export(&self) -> Result<Vec<u8>, serde_json::Error> {
+        let file = self.to_file();
+        let mut entries = Vec::new();
+        {
+            let history = self.history.lock().unwrap();
+            if let Some(path) = &history.path {
+                read_history_file(path, |entry| entries.push(entry))
+                    .map_err(serde_json::Error::io)?;
+            };
+            let saved = history.entries.len() - history.unsaved;
+            entries.extend(history.entries.iter().skip(saved).cloned());
+        }
+        serde_json::to_vec_pretty(&TrackerExport {
+            file: &file,
+            history: entries,
+        })
+    }
+
+    fn append_history(&self, history_path: &Path) -> Result<(), TrackerSaveError> {
+        let mut history = self.history.lock().unwrap();
+        if history.unsaved == 0 {
+            return Ok(());
+        };
+        let mut lines = Vec::new();
+        if history.missing_newline {
+            lines.push(b'\n');
+        };
+        let saved = history.entries.len() - history.unsaved;
+        for entry in history.entries.iter().skip(saved) {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(history_path)
+            .and_then(|mut file| {
+                file.write_all(&lines)?;
+                file.sync_data()
+            })
+            .map_err(|err| TrackerSaveError::CantWriteHistory(err, history_path.to_path_buf()))?;
+        history.unsaved = 0;
+        history.missing_newline = false;
+        history.path = Some(history_path.to_path_buf());
+        history.forget_oldest();
         Ok(())
     }
 }
+
+/// Read the history file, keeping only its last [`KEPT_HISTORY_ENTRIES`] entries in memory
+fn load_history(history_path: &Path) -> Result<History, TrackerReadError> {
+    let mut history = History::default();
+    let read = read_history_file(history_path, |entry| {
+        history.last_read.insert(entry.comic.clone(), entry.time);
+        history.entries.push_back(entry);
+        history.forget_oldest();
+    });
+    match read {
+        Ok(missing_newline) => history.missing_newline = missing_newline,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(History::default()),
+        Err(err) => {
+            return Err(TrackerReadError::CantOpenFile(
+                err,
+                history_path.to_path_buf(),
+            ))
+        }
+    };
+    history.path = Some(history_path.to_path_buf());
+    Ok(history)
+}
+
+/// Give each entry of the history file to `on_entry`, from the oldest to the newest. The lines
+/// that can't be decoded (like a line truncated by a crash) are skipped. Return true if the
+/// file doesn't end with a newline.
+fn read_history_file<F: FnMut(HistoryEntry)>(
+    history_path: &Path,
+    mut on_entry: F,
+) -> io::Result<bool> {
+    let mut reader = io::BufReader::new(File::open(history_path)?);
+    let mut line = Vec::new();
+    let mut missing_newline = false;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(missing_newline);
+        };
+        missing_newline = line.last() != Some(&b'\n');
+        if let Ok(entry) = serde_json::from_slice(&line) {
+            on_entry(entry);
+        };
+    }
+}
+
+/// Keep the current version of the file at `path` as a new backup. It is hard linked if
+/// possible, so the file is never missing.
+fn back_up(path: &Path) -> Result<(), TrackerSaveError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(format!(".{}.bak", timestamp));
+    let backup = PathBuf::from(backup);
+    if backup.exists() {
+        // already backed up in the same millisecond
+        return Ok(());
+    };
+    fs::hard_link(path, &backup)
+        .or_else(|_| fs::copy(path, &backup).map(|_| ()))
+        .map_err(|err| TrackerSaveError::CantBackUpFile(err, path.to_path_buf()))
+}
+
+/// The backups of the file at `path`, from the oldest to the newest
+fn list_backups(path: &Path) -> Vec<PathBuf> {
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(file_name) => format!("{}.", file_name),
+        None => return Vec::new(),
+    };
+    let directory = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut backups: Vec<(u128, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let timestamp = name
+                .to_str()?
+                .strip_prefix(&file_name)?
+                .strip_suffix(".bak")?
+                .parse()
+                .ok()?;
+            Some((timestamp, path.with_file_name(name)))
+        })
+        .collect();
+    backups.sort();
+    backups.into_iter().map(|(_, backup)| backup).collect()
+}
+
+/// The tracker of each user, and the shared one used when nobody is logged in (or when the
+/// accounts are disabled).
+///
+/// The progress of a user is saved next to the shared one, with the user name before the
+/// extension (like `progress.alice.json` for `progress.json`). It is read the first time the
+/// user need it.
+pub struct UserTrackers {
+    shared_path: PathBuf,
+    shared: Arc<Tracker>,
+    users: Mutex<HashMap<String, Arc<Tracker>>>,
+}
+
+impl UserTrackers {
+    /// Use `shared`, saved at `shared_path`, for the visitors that aren't logged in
+    pub fn new(shared_path: PathBuf, shared: Tracker) -> Self {
+        Self {
+            shared_path,
+            shared: Arc::new(shared),
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The file the progress of this user (or the shared progress, for `None`) is saved to
+    pub fn path_of(&self, user: Option<&str>) -> PathBuf {
+        let user = match user {
+            Some(user) => user,
+            None => return self.shared_path.clone(),
+        };
+        let mut file_name = self
+            .shared_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_os_string();
+        file_name.push(".");
+        file_name.push(user);
+        if let Some(extension) = self.shared_path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        };
+        self.shared_path.with_file_name(file_name)
+    }
+
+    /// The tracker of this user, or the shared one for `None`. A user without saved progress
+    /// start with an empty tracker.
+    pub fn get(&self, user: Option<&str>) -> Result<Arc<Tracker>, TrackerReadError> {
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(self.shared.clone()),
+        };
+        let mut users = self.users.lock().unwrap();
+        if let Some(tracker) = users.get(user) {
+            return Ok(tracker.clone());
+        };
+        let tracker = Arc::new(Tracker::load(&self.path_of(Some(user)))?);
+        users.insert(user.to_string(), tracker.clone());
+        Ok(tracker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    /// The path of a progress file in an empty folder, unique to this test
+    fn test_path(name: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("marblecomic-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder.join("progress.json")
+    }
+
+    fn progress_of(tracker: &Tracker, comic: usize) -> Option<(usize, usize)> {
+        tracker
+            .data
+            .lock()
+            .unwrap()
+            .get(&comic.to_string())
+            .copied()
+    }
+
+    #[test]
+    fn saved_progress_is_loaded_back() {
+        let path = test_path("tracker-save");
+        let tracker = Tracker::load(&path).unwrap();
+        tracker.set_progress(&ComicRef::Id(1), 2, 3, false);
+        tracker.set_progress(&ComicRef::Id(1), 2, 4, false);
+        tracker.save(&path, 0).unwrap();
+
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert!(!names.iter().any(|name| name.ends_with(".tmp")));
+        assert!(!names.iter().any(|name| name.ends_with(".bak")));
+
+        let loaded = Tracker::load(&path).unwrap();
+        assert_eq!(progress_of(&loaded, 1), Some((2, 4)));
+        assert_eq!(loaded.history(0, 10).len(), 2);
+        assert!(loaded.take_recovered_from().is_none());
+
+        // the history already saved isn't appended again
+        loaded.save(&path, 0).unwrap();
+        assert_eq!(Tracker::load(&path).unwrap().history(0, 10).len(), 2);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn backups_are_rotated() {
+        let path = test_path("tracker-backups");
+        let tracker = Tracker::load(&path).unwrap();
+        for page in 0..5 {
+            tracker.set_progress(&ComicRef::Id(1), 0, page, false);
+            tracker.save(&path, 2).unwrap();
+            // the backups are named after the millisecond they are made in
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let backups = list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        let newest = Tracker::new_from_reader(File::open(&backups[1]).unwrap()).unwrap();
+        assert_eq!(progress_of(&newest, 1), Some((0, 3)));
+        let oldest = Tracker::new_from_reader(File::open(&backups[0]).unwrap()).unwrap();
+        assert_eq!(progress_of(&oldest, 1), Some((0, 2)));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn damaged_file_is_recovered_from_the_newest_valid_backup() {
+        let path = test_path("tracker-recover");
+        let tracker = Tracker::load(&path).unwrap();
+        tracker.set_progress(&ComicRef::Id(1), 0, 1, false);
+        tracker.save(&path, 5).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        tracker.set_progress(&ComicRef::Id(1), 0, 2, false);
+        tracker.save(&path, 5).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        tracker.set_progress(&ComicRef::Id(1), 0, 3, false);
+        tracker.save(&path, 5).unwrap();
+
+        // a truncated file, and a damaged newest backup
+        fs::write(&path, "{\"progress\": {").unwrap();
+        let backups = list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        fs::write(&backups[1], "").unwrap();
+
+        let recovered = Tracker::load(&path).unwrap();
+        assert_eq!(progress_of(&recovered, 1), Some((0, 1)));
+        assert_eq!(recovered.take_recovered_from(), Some(backups[0].clone()));
+        assert_eq!(recovered.take_recovered_from(), None);
+
+        fs::write(&backups[0], "[]").unwrap();
+        assert!(matches!(
+            Tracker::load(&path),
+            Err(TrackerReadError::CantDecodeFile(..))
+        ));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn concurrent_saves_keep_a_valid_file() {
+        let path = test_path("tracker-concurrent");
+        let tracker = Arc::new(Tracker::load(&path).unwrap());
+        let threads: Vec<_> = (0..8)
+            .map(|comic| {
+                let tracker = tracker.clone();
+                let path = path.clone();
+                thread::spawn(move || {
+                    tracker.set_progress(&ComicRef::Id(comic), 0, 1, false);
+                    tracker.save(&path, 1)
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        // the last save contain the progress set before all the saves
+        let loaded = Tracker::load(&path).unwrap();
+        for comic in 0..8 {
+            assert_eq!(progress_of(&loaded, comic), Some((0, 1)));
+        }
+        assert_eq!(loaded.history(0, 10).len(), 8);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_the_recent_history_is_kept_in_memory() {
+        let path = test_path("tracker-history-cap");
+        let tracker = Tracker::load(&path).unwrap();
+        tracker.set_progress(&ComicRef::Id(1), 0, 0, false);
+        for page in 0..KEPT_HISTORY_ENTRIES {
+            tracker.set_progress(&ComicRef::Id(2), 0, page, false);
+        }
+        // the unsaved entries are all kept
+        assert_eq!(tracker.history(0, usize::MAX).len(), KEPT_HISTORY_ENTRIES + 1);
+        tracker.save(&path, 0).unwrap();
+        assert_eq!(tracker.history(0, usize::MAX).len(), KEPT_HISTORY_ENTRIES);
+
+        let loaded = Tracker::load(&path).unwrap();
+        assert_eq!(loaded.history(0, usize::MAX).len(), KEPT_HISTORY_ENTRIES);
+        let page = loaded.history(10, 2);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].image, KEPT_HISTORY_ENTRIES - 11);
+        assert_eq!(page[1].image, KEPT_HISTORY_ENTRIES - 12);
+        assert!(loaded.history(KEPT_HISTORY_ENTRIES, 10).is_empty());
+        // the comic whose entry was dropped still has its last read time
+        assert!(loaded.last_read_times().contains_key("1"));
+
+        // the export still contain the whole history, including the unsaved entries
+        loaded.set_progress(&ComicRef::Id(3), 0, 0, false);
+        let export: serde_json::Value = serde_json::from_slice(&loaded.export().unwrap()).unwrap();
+        let history = export["history"].as_array().unwrap();
+        assert_eq!(history.len(), KEPT_HISTORY_ENTRIES + 2);
+        assert_eq!(history[0]["comic"], "1");
+        assert_eq!(history[KEPT_HISTORY_ENTRIES + 1]["comic"], "3");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
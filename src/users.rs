@@ -0,0 +1,120 @@
+use rand::RngCore;
+use thiserror::Error;
+
+use std::collections::BTreeMap;
+use std::fs::{rename, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+#[derive(Error, Debug)]
+pub enum UserStoreError {
+    #[error("can't open the user file at {1}")]
+    CantOpenFile(#[source] io::Error, PathBuf),
+    #[error("can't parse the user file at {1}")]
+    CantParseFile(#[source] serde_json::Error, PathBuf),
+    #[error("can't write the user file at {1}")]
+    CantWriteFile(#[source] io::Error, PathBuf),
+    #[error("the user name {0:?} is invalid (it should only contain ascii letters, digits, '-' and '_')")]
+    InvalidName(String),
+    #[error("can't hash the password")]
+    CantHashPassword(#[source] argon2::Error),
+}
+
+/// Whether this can be used as a user name. It is also used in the name of the user files, so
+/// it is restricted to ascii letters, digits, `-` and `_`.
+pub fn is_valid_user_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+}
+
+/// The local user accounts, with their argon2 password hash, saved as a json file mapping each
+/// user name to its hash
+#[derive(Debug)]
+pub struct UserStore {
+    path: PathBuf,
+    users: RwLock<BTreeMap<String, String>>,
+}
+
+impl UserStore {
+    /// Read the users saved at `path`. There is no user if there is no such file.
+    pub fn load(path: PathBuf) -> Result<Self, UserStoreError> {
+        let users = if path.exists() {
+            let file =
+                File::open(&path).map_err(|err| UserStoreError::CantOpenFile(err, path.clone()))?;
+            serde_json::from_reader(BufReader::new(file))
+                .map_err(|err| UserStoreError::CantParseFile(err, path.clone()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path,
+            users: RwLock::new(users),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.users.read().unwrap().contains_key(name)
+    }
+
+    /// The user names, in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        self.users.read().unwrap().keys().cloned().collect()
+    }
+
+    /// True if this user exist, and this is its password
+    pub fn check_password(&self, name: &str, password: &str) -> bool {
+        match self.users.read().unwrap().get(name) {
+            Some(hash) => argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Set the password of a user, creating it if it doesn't exist. It is only saved by
+    /// [`UserStore::save`].
+    pub fn set_password(&self, name: &str, password: &str) -> Result<(), UserStoreError> {
+        if !is_valid_user_name(name) {
+            return Err(UserStoreError::InvalidName(name.to_string()));
+        };
+        let mut salt = [0; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            ..argon2::Config::default()
+        };
+        let hash = argon2::hash_encoded(password.as_bytes(), &salt, &config)
+            .map_err(UserStoreError::CantHashPassword)?;
+        self.users.write().unwrap().insert(name.to_string(), hash);
+        Ok(())
+    }
+
+    /// Remove a user. Return false if it doesn't exist.
+    pub fn remove(&self, name: &str) -> bool {
+        self.users.write().unwrap().remove(name).is_some()
+    }
+
+    /// Write the users to their file. The content is written to a temporary file first, so an
+    /// interrupted save doesn't lose the accounts.
+    pub fn save(&self) -> Result<(), UserStoreError> {
+        let mut temporary_path = self.path.as_os_str().to_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let write_temporary_file = || -> io::Result<()> {
+            let mut file = File::create(&temporary_path)?;
+            serde_json::to_writer_pretty(&mut file, &*self.users.read().unwrap())?;
+            file.write_all(b"\n")?;
+            file.sync_all()
+        };
+        write_temporary_file()
+            .map_err(|err| UserStoreError::CantWriteFile(err, temporary_path.clone()))?;
+        rename(&temporary_path, &self.path)
+            .map_err(|err| UserStoreError::CantWriteFile(err, self.path.clone()))
+    }
+}